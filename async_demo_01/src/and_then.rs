@@ -0,0 +1,103 @@
+use std::task::Waker;
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+// 例子三
+/*
+    AndThenFut 先运行 first，first 完成后把它的结果交给闭包 f，
+    由 f 根据这个结果构建出第二个 Future，再运行第二个 Future
+
+    最早的版本里 second 是提前构建好的，它拿不到 first 的结果，
+    所以两个 Future 的 Output 都只能是 ()；
+    现在 second 是在 first 完成之后才被构建的，结果可以一步一步地往下传
+
+    状态：
+    1. First：还在运行 first，闭包 f 还没有被调用
+    2. Second：first 已经完成，正在运行 f 构建出的第二个 Future
+    3. Done：已经完成了
+*/
+pub struct AndThenFut<FutureA, FutureB, F> {
+    state: State<FutureA, FutureB, F>,
+}
+
+enum State<FutureA, FutureB, F> {
+    First(FutureA, F),
+    Second(FutureB),
+    Done,
+}
+
+impl<FutureA, FutureB, F> AndThenFut<FutureA, FutureB, F> {
+    pub fn new(first: FutureA, f: F) -> Self {
+        AndThenFut {
+            state: State::First(first, f),
+        }
+    }
+}
+
+impl<FutureA, FutureB, F> SimpleFuture for AndThenFut<FutureA, FutureB, F>
+where
+    FutureA: SimpleFuture,
+    FutureB: SimpleFuture,
+    F: FnOnce(FutureA::Output) -> FutureB,
+{
+    type Output = FutureB::Output;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        // 如果还在 First 状态，就调用 first 的 poll 方法
+        // 如果返回 Ready 就说明第一个完成了，用它的结果构建出第二个 Future，然后接着 poll 第二个
+        // 如果 first 没有完成就返回 Pending
+        if let State::First(first, _) = &mut self.state {
+            let output = match first.poll(waker) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return Poll::Pending,
+            };
+            let State::First(_, f) = std::mem::replace(&mut self.state, State::Done) else {
+                unreachable!()
+            };
+            self.state = State::Second(f(output));
+        }
+
+        match &mut self.state {
+            State::Second(second) => {
+                let poll = second.poll(waker);
+                if poll.is_ready() {
+                    self.state = State::Done;
+                }
+                poll
+            }
+            _ => panic!("AndThenFut polled after completion"),
+        }
+    }
+}
+
+/*
+    Map 在 Future 完成后用闭包 f 转换它的结果，不会再运行另一个 Future
+*/
+pub struct Map<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F> Map<Fut, F> {
+    pub fn new(future: Fut, f: F) -> Self {
+        Map { future, f: Some(f) }
+    }
+}
+
+impl<Fut, F, T> SimpleFuture for Map<Fut, F>
+where
+    Fut: SimpleFuture,
+    F: FnOnce(Fut::Output) -> T,
+{
+    type Output = T;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<T> {
+        match self.future.poll(waker) {
+            Poll::Ready(output) => {
+                let f = self.f.take().expect("Map polled after completion");
+                Poll::Ready(f(output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+/*
+    SimpleFuture 和 std::future::Future 之间的适配器
+
+    - IntoStdFuture：把 SimpleFuture 包装成 std 的 Future，这样就可以 `.await` 它，
+      或者交给 `futures::executor::block_on` 等执行者去运行
+    - FromStdFuture：把 std 的 Future（比如 async 块）包装成 SimpleFuture，
+      这样它就可以放进 Join、AndThenFut 中
+*/
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Waker},
+};
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/// 可以被 `.await` 的 SimpleFuture
+pub struct IntoStdFuture<F>(F);
+
+// SimpleFuture::poll 接收的是 `&mut self`，本来就允许在两次 poll 之间移动，
+// 所以包装之后也不需要被 Pin 住
+impl<F> Unpin for IntoStdFuture<F> {}
+
+impl<F: SimpleFuture> Future for IntoStdFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> std::task::Poll<Self::Output> {
+        self.get_mut().0.poll(cx.waker()).into()
+    }
+}
+
+impl<F> IntoStdFuture<F> {
+    pub(crate) fn new(future: F) -> Self {
+        IntoStdFuture(future)
+    }
+
+    pub fn into_inner(self) -> F {
+        self.0
+    }
+}
+
+/// 当作 SimpleFuture 使用的 std Future
+///
+/// std 的 Future 可能是自引用的（比如 async 块），不能在 poll 之间移动，
+/// 所以先把它 Box::pin 到堆上，之后移动的只是 Box 指针
+pub struct FromStdFuture<F: Future>(Pin<Box<F>>);
+
+impl<F: Future> SimpleFuture for FromStdFuture<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let mut cx = Context::from_waker(waker);
+        self.0.as_mut().poll(&mut cx).into()
+    }
+}
+
+/// 把 std 的 Future 转换成 SimpleFuture
+pub fn from_std<F: Future>(future: F) -> FromStdFuture<F> {
+    FromStdFuture(Box::pin(future))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Join, SimpleFutureExt};
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn join_and_and_then_can_be_awaited() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let push = |name: &'static str| {
+            let log = log.clone();
+            from_std(async move { log.lock().unwrap().push(name) })
+        };
+
+        let join = Join::new(push("a"), push("b"));
+        let and_then = push("first").then(|()| push("second"));
+        block_on(async {
+            assert_eq!(join.into_std().await, ((), ()));
+            and_then.into_std().await;
+        });
+        assert_eq!(*log.lock().unwrap(), vec!["a", "b", "first", "second"]);
+    }
+
+    #[test]
+    fn pending_std_future_is_woken_through_simple_future() {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || tx.send(7).unwrap());
+        let simple = from_std(rx);
+        assert_eq!(block_on(simple.into_std()), Ok(7));
+    }
+}
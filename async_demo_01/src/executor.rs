@@ -0,0 +1,136 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    最小的执行者：在当前线程上运行一个 SimpleFuture，直到它完成
+
+    1. poll 根 Future（比如一个 Join 或 AndThenFut），waker 会一层层地传给所有子 Future
+    2. 返回 Pending 时，当前线程就 park（睡眠），不会空转占用 CPU
+    3. 某个子 Future 有进展时调用 waker.wake()，它会 unpark 这个线程
+    4. 线程醒来后再 poll 一次根 Future，回到第 1 步
+
+    wake 可能在 poll 的过程中、甚至在 park 之前就已经被调用了，
+    所以用 notified 标记记住“有人唤醒过”，park 之前先检查它，这样唤醒就不会丢。
+    park 也可能在没有人唤醒的时候返回（spurious wakeup），所以要放在循环里
+*/
+
+/// 唤醒时 unpark 运行执行者的线程
+struct ThreadWaker {
+    thread: Thread,
+    notified: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// 在当前线程上运行 future 直到完成，并返回它的结果
+pub fn block_on<F: SimpleFuture>(mut future: F) -> F::Output {
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        notified: AtomicBool::new(false),
+    });
+    let waker = Waker::from(thread_waker.clone());
+
+    loop {
+        if let Poll::Ready(output) = future.poll(&waker) {
+            return output;
+        }
+        while !thread_waker.notified.swap(false, Ordering::Acquire) {
+            thread::park();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{join, FuturesUnordered, SimpleFutureExt, SimpleStreamExt};
+    use std::{sync::Mutex, time::Duration};
+
+    // 第一次 poll 时把 waker 交给另一个线程，那个线程过一会儿再设置结果并唤醒
+    struct Delayed {
+        delay: Duration,
+        value: u32,
+        state: Arc<Mutex<Option<u32>>>,
+        started: bool,
+    }
+
+    fn delayed(millis: u64, value: u32) -> Delayed {
+        Delayed {
+            delay: Duration::from_millis(millis),
+            value,
+            state: Arc::new(Mutex::new(None)),
+            started: false,
+        }
+    }
+
+    impl SimpleFuture for Delayed {
+        type Output = u32;
+
+        fn poll(&mut self, waker: &Waker) -> Poll<u32> {
+            if let Some(value) = *self.state.lock().unwrap() {
+                return Poll::Ready(value);
+            }
+            if !self.started {
+                self.started = true;
+                let (delay, value, state, waker) =
+                    (self.delay, self.value, self.state.clone(), waker.clone());
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    *state.lock().unwrap() = Some(value);
+                    waker.wake();
+                });
+            }
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn runs_join_and_and_then_trees() {
+        let tree = join(delayed(30, 1), delayed(10, 2).then(|n| delayed(10, n * 10)));
+        assert_eq!(block_on(tree), (1, 20));
+    }
+
+    #[test]
+    fn wake_before_park_is_not_lost() {
+        // 在 poll 中就同步地调用 wake，如果唤醒丢了，block_on 会永远 park
+        let mut first = true;
+        let yielding = crate::from_std(futures::future::poll_fn(move |cx| {
+            if first {
+                first = false;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            } else {
+                std::task::Poll::Ready(7)
+            }
+        }));
+        assert_eq!(block_on(yielding), 7);
+    }
+
+    #[test]
+    fn drives_futures_unordered_from_other_threads() {
+        let set: FuturesUnordered<_> = (0..50).map(|i| delayed(50 - i, i as u32)).collect();
+        let mut order = block_on(set.fold(Vec::new(), |mut order, n| {
+            order.push(n);
+            order
+        }));
+        order.sort_unstable();
+        assert_eq!(order, (0..50).collect::<Vec<_>>());
+    }
+}
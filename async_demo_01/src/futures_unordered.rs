@@ -0,0 +1,264 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Wake, Waker},
+};
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    FuturesUnordered 是一组并发运行的同类型 Future，谁先完成就先返回谁的结果
+
+    Join 只有一个 waker：任何一个子 Future 被唤醒，所有没完成的子 Future 都要重新 poll 一遍，
+    子 Future 很多时这就很浪费了。FuturesUnordered 给每个子 Future 一个自己的 waker：
+    1. 子 Future 的 waker 被调用时，把自己的下标放进就绪队列，再唤醒外层的任务
+    2. 外层 poll 时只从就绪队列中取出下标，poll 对应的子 Future
+    3. 一个子 Future 在被 poll 之前，不管被唤醒了多少次，都只会在就绪队列中出现一次
+
+    这样每次 poll 的开销只和被唤醒的子 Future 的个数有关，和总数无关，
+    即使同时有几万个 Future 在等待也没有问题
+
+    结果通过 `poll_next` 按完成的顺序一个一个地返回，全部完成后返回 `Ready(None)`，
+    它也实现了 `futures::Stream`，可以配合 StreamExt 使用
+*/
+pub struct FuturesUnordered<F> {
+    /// 子 Future 按下标存放，完成的位置变成 None，下标放进 free 中留给之后 push 的 Future 复用
+    slots: Vec<Option<Child<F>>>,
+    free: Vec<usize>,
+    len: usize,
+    ready: Arc<ReadyQueue>,
+}
+
+struct Child<F> {
+    future: F,
+    waker: Arc<ChildWaker>,
+}
+
+/// 就绪队列，以及外层任务的 waker
+#[derive(Default)]
+struct ReadyQueue {
+    queue: Mutex<VecDeque<usize>>,
+    parent: Mutex<Option<Waker>>,
+}
+
+/// 每个子 Future 自己的 waker
+struct ChildWaker {
+    index: usize,
+
+    /// 是否已经在就绪队列中了，避免重复入队
+    queued: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for ChildWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.ready.queue.lock().unwrap().push_back(self.index);
+        let parent = self.ready.parent.lock().unwrap().clone();
+        if let Some(parent) = parent {
+            parent.wake();
+        }
+    }
+}
+
+impl<F> FuturesUnordered<F> {
+    pub fn new() -> Self {
+        FuturesUnordered {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            ready: Arc::new(ReadyQueue::default()),
+        }
+    }
+
+    /// 还没有完成的 Future 的个数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 加入一个 Future，它会在下一次 `poll_next` 时第一次被 poll
+    pub fn push(&mut self, future: F) {
+        let index = self.free.pop().unwrap_or(self.slots.len());
+        let waker = Arc::new(ChildWaker {
+            index,
+            queued: AtomicBool::new(true),
+            ready: self.ready.clone(),
+        });
+        let child = Child { future, waker };
+        if index == self.slots.len() {
+            self.slots.push(Some(child));
+        } else {
+            self.slots[index] = Some(child);
+        }
+        self.len += 1;
+        self.ready.queue.lock().unwrap().push_back(index);
+    }
+}
+
+impl<F: SimpleFuture> FuturesUnordered<F> {
+    /// 返回下一个完成的 Future 的结果，全部完成后返回 `Ready(None)`
+    pub fn poll_next(&mut self, waker: &Waker) -> Poll<Option<F::Output>> {
+        // 先登记外层的 waker，再去取就绪队列，这样之后的唤醒都不会丢
+        *self.ready.parent.lock().unwrap() = Some(waker.clone());
+
+        // 一次最多 poll 当前个数那么多次：如果有子 Future 每次 poll 都立刻唤醒自己，
+        // 不加限制的话这里就永远不会返回，同一个任务中的其他 Future 就饿死了
+        let budget = self.len;
+        for _ in 0..budget {
+            let Some(index) = self.ready.queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            // 已经完成的子 Future 的旧 waker 可能还会被调用，这时对应的位置是空的或者已经被复用了，
+            // 复用的情况下多 poll 一次也没关系
+            let Some(child) = self.slots[index].as_mut() else {
+                continue;
+            };
+
+            // 在 poll 之前清除标记，poll 的过程中被唤醒时还能再次入队
+            child.waker.queued.store(false, Ordering::Release);
+            let child_waker = Waker::from(child.waker.clone());
+            if let Poll::Ready(output) = child.future.poll(&child_waker) {
+                self.slots[index] = None;
+                self.free.push(index);
+                self.len -= 1;
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if self.is_empty() {
+            Poll::Ready(None)
+        } else {
+            if !self.ready.queue.lock().unwrap().is_empty() {
+                // 用完了这次的配额但还有就绪的子 Future，让执行者稍后再 poll
+                waker.wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl<F> Default for FuturesUnordered<F> {
+    fn default() -> Self {
+        FuturesUnordered::new()
+    }
+}
+
+impl<F> FromIterator<F> for FuturesUnordered<F> {
+    fn from_iter<I: IntoIterator<Item = F>>(iter: I) -> Self {
+        let mut set = FuturesUnordered::new();
+        for future in iter {
+            set.push(future);
+        }
+        set
+    }
+}
+
+// 子 Future 是 SimpleFuture，本来就可以移动
+impl<F> Unpin for FuturesUnordered<F> {}
+
+impl<F: SimpleFuture> futures::Stream for FuturesUnordered<F> {
+    type Item = F::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> std::task::Poll<Option<F::Output>> {
+        FuturesUnordered::poll_next(self.get_mut(), cx.waker()).into()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_std;
+    use futures::{channel::oneshot, executor::block_on, task::noop_waker, StreamExt};
+    use std::{cell::Cell, rc::Rc};
+
+    // 记录被 poll 的次数，由外面决定什么时候完成
+    struct Probe {
+        polls: Rc<Cell<usize>>,
+        done: Rc<Cell<bool>>,
+        waker: Rc<Cell<Option<Waker>>>,
+        id: usize,
+    }
+
+    impl SimpleFuture for Probe {
+        type Output = usize;
+
+        fn poll(&mut self, waker: &Waker) -> Poll<usize> {
+            self.polls.set(self.polls.get() + 1);
+            if self.done.get() {
+                Poll::Ready(self.id)
+            } else {
+                self.waker.set(Some(waker.clone()));
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn only_woken_children_are_polled() {
+        let polls = Rc::new(Cell::new(0));
+        let handles: Vec<_> = (0..1000)
+            .map(|_| (Rc::new(Cell::new(false)), Rc::new(Cell::new(None))))
+            .collect();
+        let mut set: FuturesUnordered<_> = handles
+            .iter()
+            .enumerate()
+            .map(|(id, (done, waker))| Probe {
+                polls: polls.clone(),
+                done: done.clone(),
+                waker: waker.clone(),
+                id,
+            })
+            .collect();
+
+        let parent = noop_waker();
+        assert!(set.poll_next(&parent).is_pending());
+        assert_eq!(polls.get(), 1000);
+
+        // 只唤醒第 42 个，之后只有它会被 poll
+        let (done, waker) = &handles[42];
+        done.set(true);
+        waker.take().unwrap().wake();
+        assert!(matches!(set.poll_next(&parent), Poll::Ready(Some(42))));
+        assert_eq!(polls.get(), 1001);
+        assert!(set.poll_next(&parent).is_pending());
+        assert_eq!(polls.get(), 1001);
+        assert_eq!(set.len(), 999);
+    }
+
+    #[test]
+    fn collects_many_futures_woken_from_another_thread() {
+        const N: usize = 20_000;
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..N).map(|_| oneshot::channel()).unzip();
+        let set: FuturesUnordered<_> = receivers.into_iter().map(from_std).collect();
+
+        let sender = std::thread::spawn(move || {
+            for (i, tx) in senders.into_iter().enumerate().rev() {
+                tx.send(i).unwrap();
+            }
+        });
+
+        let mut results: Vec<usize> = block_on(set.map(Result::unwrap).collect());
+        sender.join().unwrap();
+
+        results.sort_unstable();
+        assert_eq!(results, (0..N).collect::<Vec<_>>());
+    }
+}
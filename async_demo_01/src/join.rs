@@ -0,0 +1,239 @@
+use std::task::Waker;
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    MaybeDone 记录一个子 Future 的状态：
+    1. Future：还没完成，下次还要 poll 它
+    2. Done：已经完成了，先把结果存起来，等其他子 Future 都完成后再一起返回
+    3. Gone：结果已经被取走了
+
+    已经完成的子 Future 不会再被 poll
+*/
+pub(crate) enum MaybeDone<F: SimpleFuture> {
+    Future(F),
+    Done(F::Output),
+    Gone,
+}
+
+impl<F: SimpleFuture> MaybeDone<F> {
+    // 返回 true 表示这个子 Future 已经有结果了
+    pub(crate) fn poll(&mut self, waker: &Waker) -> bool {
+        match self {
+            MaybeDone::Future(future) => match future.poll(waker) {
+                Poll::Ready(output) => {
+                    *self = MaybeDone::Done(output);
+                    true
+                }
+                Poll::Pending => false,
+            },
+            MaybeDone::Done(_) => true,
+            MaybeDone::Gone => panic!("MaybeDone polled after its output was taken"),
+        }
+    }
+
+    pub(crate) fn take_output(&mut self) -> F::Output {
+        match std::mem::replace(self, MaybeDone::Gone) {
+            MaybeDone::Done(output) => output,
+            _ => panic!("MaybeDone::take_output called before completion"),
+        }
+    }
+}
+
+// 例子二
+/*
+    Join 中有两个 Future
+    它的作用就是并发的让这两个 Future 来完成，
+    两个都完成后返回 (a 的结果, b 的结果)
+*/
+pub struct Join<FutureA: SimpleFuture, FutureB: SimpleFuture> {
+    a: MaybeDone<FutureA>,
+    b: MaybeDone<FutureB>,
+}
+
+impl<FutureA: SimpleFuture, FutureB: SimpleFuture> Join<FutureA, FutureB> {
+    pub fn new(a: FutureA, b: FutureB) -> Self {
+        Join {
+            a: MaybeDone::Future(a),
+            b: MaybeDone::Future(b),
+        }
+    }
+}
+
+impl<FutureA, FutureB> SimpleFuture for Join<FutureA, FutureB>
+where
+    FutureA: SimpleFuture,
+    FutureB: SimpleFuture,
+{
+    type Output = (FutureA::Output, FutureB::Output);
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        // 每个子 Future 都要 poll 一下（已经完成的会直接跳过），
+        // 这样没完成的子 Future 都把 waker 登记好了
+        let a_done = self.a.poll(waker);
+        let b_done = self.b.poll(waker);
+
+        // 两个都有结果了才算完成，否则就返回 Pending，Pending 就表示这里至少还有一个 Future 未完成
+        if a_done && b_done {
+            Poll::Ready((self.a.take_output(), self.b.take_output()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// 并发地完成两个 Future
+pub fn join<A: SimpleFuture, B: SimpleFuture>(a: A, b: B) -> Join<A, B> {
+    Join::new(a, b)
+}
+
+// Join3、Join4 和 Join 的写法完全一样，只是子 Future 的个数不同，所以用宏来生成
+macro_rules! generate_join {
+    ($(#[$doc:meta])* $name:ident, $fn_name:ident, $($F:ident $f:ident),+) => {
+        $(#[$doc])*
+        pub struct $name<$($F: SimpleFuture),+> {
+            $($f: MaybeDone<$F>,)+
+        }
+
+        impl<$($F: SimpleFuture),+> SimpleFuture for $name<$($F),+> {
+            type Output = ($($F::Output,)+);
+
+            fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+                let mut all_done = true;
+                $(all_done &= self.$f.poll(waker);)+
+                if all_done {
+                    Poll::Ready(($(self.$f.take_output(),)+))
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+
+        $(#[$doc])*
+        pub fn $fn_name<$($F: SimpleFuture),+>($($f: $F),+) -> $name<$($F),+> {
+            $name {
+                $($f: MaybeDone::Future($f),)+
+            }
+        }
+    };
+}
+
+generate_join!(
+    /// 并发地完成三个 Future
+    Join3, join3, A a, B b, C c
+);
+generate_join!(
+    /// 并发地完成四个 Future
+    Join4, join4, A a, B b, C c, D d
+);
+
+/*
+    JoinAll 并发地完成任意多个同类型的 Future，
+    所有的都完成后，按照传入的顺序返回结果
+*/
+pub struct JoinAll<F: SimpleFuture> {
+    children: Vec<MaybeDone<F>>,
+}
+
+/// 并发地完成一组 Future，结果的顺序和传入的顺序一致
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: SimpleFuture,
+{
+    JoinAll {
+        children: futures.into_iter().map(MaybeDone::Future).collect(),
+    }
+}
+
+impl<F: SimpleFuture> SimpleFuture for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let mut all_done = true;
+        for child in &mut self.children {
+            all_done &= child.poll(waker);
+        }
+        if all_done {
+            Poll::Ready(
+                self.children
+                    .iter_mut()
+                    .map(MaybeDone::take_output)
+                    .collect(),
+            )
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // 第 n 次被 poll 时才完成，并记录一共被 poll 了多少次
+    struct CountDown<T> {
+        remaining: usize,
+        polls: Rc<Cell<usize>>,
+        value: Option<T>,
+    }
+
+    fn count_down<T>(remaining: usize, value: T) -> (CountDown<T>, Rc<Cell<usize>>) {
+        let polls = Rc::new(Cell::new(0));
+        let future = CountDown {
+            remaining,
+            polls: polls.clone(),
+            value: Some(value),
+        };
+        (future, polls)
+    }
+
+    impl<T> SimpleFuture for CountDown<T> {
+        type Output = T;
+
+        fn poll(&mut self, _waker: &Waker) -> Poll<T> {
+            self.polls.set(self.polls.get() + 1);
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                Poll::Ready(self.value.take().unwrap())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    fn run<F: SimpleFuture>(mut future: F) -> F::Output {
+        let waker = noop_waker();
+        loop {
+            if let Poll::Ready(output) = future.poll(&waker) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn join_returns_both_outputs_and_skips_finished_child() {
+        let (fast, fast_polls) = count_down(1, "fast");
+        let (slow, slow_polls) = count_down(3, 42);
+        assert_eq!(run(join(fast, slow)), ("fast", 42));
+        assert_eq!(fast_polls.get(), 1);
+        assert_eq!(slow_polls.get(), 3);
+    }
+
+    #[test]
+    fn join4_and_join_all_keep_order() {
+        let out = run(join4(
+            count_down(2, 1).0,
+            count_down(1, 'b').0,
+            count_down(3, "c").0,
+            count_down(1, ()).0,
+        ));
+        assert_eq!(out, (1, 'b', "c", ()));
+
+        let futures = (1..=5).rev().map(|n| count_down(n, n).0);
+        assert_eq!(run(join_all(futures)), vec![5, 4, 3, 2, 1]);
+    }
+}
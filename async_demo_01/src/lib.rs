@@ -0,0 +1,49 @@
+/*
+    手写的 SimpleFuture 以及它的组合器
+
+    - simple_future：SimpleFuture trait 和 Poll
+    - executor：block_on，在当前线程上运行 SimpleFuture，没有进展时 park，被唤醒后再 poll
+    - join：Join、join3、join4、JoinAll，并发地完成多个 Future，并返回所有的结果
+    - and_then：AndThenFut、Map，一个完成之后把结果交给闭包，再运行闭包返回的 Future
+    - try_join：TryJoin、try_join3、try_join4、TryJoinAll，并发地完成多个结果是 Result 的 Future，遇到第一个 Err 就返回
+    - macros：join!、try_join!、select! 宏，基于上面的组合器，可以在任何执行者中使用
+    - try_future：TryFutureExt，结果是 Result 的 Future 的 and_then、or_else、map_ok、map_err、err_into 等
+    - futures_unordered：FuturesUnordered，每个子 Future 有自己的 waker，按完成的顺序返回结果
+    - select：Select、SelectAll，等待最先完成的那个 Future
+    - compat：和 std::future::Future 互相转换
+    - stream、sink：SimpleStream、SimpleSink 以及它们的组合器，可以和 futures 的 Stream、Sink 互相转换
+    - socket：非阻塞的 Socket，以及读写它的 SocketRead、SocketWrite
+*/
+
+mod and_then;
+mod compat;
+mod executor;
+mod futures_unordered;
+mod join;
+mod macros;
+mod select;
+mod simple_future;
+mod sink;
+#[cfg(unix)]
+mod socket;
+pub mod stream;
+mod try_future;
+mod try_join;
+
+pub use and_then::{AndThenFut, Map};
+pub use compat::{from_std, FromStdFuture, IntoStdFuture};
+pub use executor::block_on;
+pub use futures_unordered::FuturesUnordered;
+pub use join::{join, join3, join4, join_all, Join, Join3, Join4, JoinAll};
+pub use select::{select, select_all, select_biased, Either, Select, SelectAll};
+pub use simple_future::{ready, Poll, Ready, SimpleFuture, SimpleFutureExt};
+pub use sink::{from_std_sink, FromStdSink, SimpleSink, SimpleSinkExt};
+#[cfg(unix)]
+pub use socket::{Socket, SocketRead, SocketWrite};
+pub use stream::{from_std_stream, SimpleStream, SimpleStreamExt};
+pub use try_future::{
+    ErrInto, MapErr, MapOk, OrElse, TryAndThen, TryFuture, TryFutureExt, UnwrapOrElse,
+};
+pub use try_join::{
+    try_join, try_join3, try_join4, try_join_all, TryJoin, TryJoin3, TryJoin4, TryJoinAll,
+};
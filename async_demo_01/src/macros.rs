@@ -0,0 +1,289 @@
+/*
+    join!、try_join!、select! 宏，用法和 futures、tokio 中的同名宏一样，只能在 async 块、async 函数中使用：
+
+    - 参数是普通的 std Future（async 块、async 函数的返回值、JoinHandle 等），
+      宏内部先用 from_std 转换成 SimpleFuture，再用 Join、TryJoin、SelectAll 组合起来，
+      最后用 into_std 转换回来 `.await`，所以可以在任何执行者中使用
+    - 手写的 SimpleFuture 可以先调用 `.into_std()` 再传进来
+
+    join!(a, b, c) 的结果是 (a 的结果, b 的结果, c 的结果)
+    try_join!(a, b, c) 的结果是 Result<(..), E>，遇到第一个 Err 就返回，其他的 Future 被 drop 掉
+
+    select! {
+        biased;                       // 可选：按书写顺序 poll，默认每次轮换起始位置
+        Some(v) = rx.next() => { .. } // 模式 = Future => 分支
+        n = timer => n + 1,
+        else => 0,                    // 可选：所有分支都完成了但都不匹配时执行
+    }
+
+    - 最先完成、并且结果匹配模式的那个分支会被执行，其他的 Future 都被 drop 掉（相当于取消）
+    - 结果不匹配模式时，这个分支就被禁用了，继续等待其他的分支
+    - 所有的分支都被禁用了就执行 else，没有 else 时 panic
+    - 分支的代码直接展开在调用的地方，可以在里面使用 `?`、`return`、`break`、`continue`
+    - 是否匹配是先通过引用检查的，所以模式中不能使用 `mut` 绑定
+*/
+
+/// 并发地等待多个 Future，返回所有的结果
+#[macro_export]
+macro_rules! join {
+    ($($fut:expr),+ $(,)?) => {
+        $crate::SimpleFutureExt::into_std(
+            $crate::__join_tree!(join [] [] [] $($fut,)+)
+        ).await
+    };
+}
+
+/// 并发地等待多个结果是 Result 的 Future，全部成功时返回所有的结果，遇到第一个 Err 就返回
+#[macro_export]
+macro_rules! try_join {
+    ($($fut:expr),+ $(,)?) => {
+        $crate::SimpleFutureExt::into_std(
+            $crate::__join_tree!(try_join [] [] [] $($fut,)+)
+        ).await
+    };
+}
+
+// 把多个 Future 从左到右两两组合起来：join(join(join(a, b), c), d)，
+// 结果是 (((a, b), c), d)，最后再用 map 展开成 (a, b, c, d)。
+// 每一层展开时引入的 `out` 都是不同的变量（宏的卫生性），所以可以用来绑定每一个结果
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __join_tree {
+    // 第一个 Future
+    ($kind:ident [] [] [] $first:expr, $($rest:expr,)*) => {
+        $crate::__join_tree!($kind [$crate::from_std($first)] [out] [out] $($rest,)*)
+    };
+    // 和下一个 Future 组合
+    ($kind:ident [$fut:expr] [$pat:pat] [$($out:ident)*] $next:expr, $($rest:expr,)*) => {
+        $crate::__join_tree!(
+            $kind
+            [$crate::__join_tree!(@combine $kind $fut, $crate::from_std($next))]
+            [($pat, out)]
+            [$($out)* out]
+            $($rest,)*
+        )
+    };
+    // 全部组合完了，展开嵌套的元组
+    (join [$fut:expr] [$pat:pat] [$($out:ident)*]) => {
+        $crate::SimpleFutureExt::map($fut, |$pat| ($($out,)*))
+    };
+    (try_join [$fut:expr] [$pat:pat] [$($out:ident)*]) => {
+        $crate::TryFutureExt::map_ok($fut, |$pat| ($($out,)*))
+    };
+    (@combine join $a:expr, $b:expr) => {
+        $crate::join($a, $b)
+    };
+    (@combine try_join $a:expr, $b:expr) => {
+        $crate::try_join($a, $b)
+    };
+}
+
+/// 等待多个 Future 中最先完成、并且结果匹配模式的那个，执行对应的分支
+#[macro_export]
+macro_rules! select {
+    (biased; $($tokens:tt)*) => {
+        $crate::__select_parse!(true [] $($tokens)*)
+    };
+    ($($tokens:tt)*) => {
+        $crate::__select_parse!(false [] $($tokens)*)
+    };
+}
+
+// 把分支一个一个地解析成 [模式] [Future] [分支代码]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_parse {
+    ($biased:tt [$($branches:tt)*] else => $else_body:expr $(,)?) => {
+        $crate::__select_index!($biased [$else_body] [] [] $($branches)*)
+    };
+    ($biased:tt [$($branches:tt)*]) => {
+        $crate::__select_index!(
+            $biased
+            [::core::panic!("all branches are disabled and there is no else branch")]
+            [] [] $($branches)*
+        )
+    };
+    ($biased:tt [$($branches:tt)*] , $($rest:tt)*) => {
+        $crate::__select_parse!($biased [$($branches)*] $($rest)*)
+    };
+    ($biased:tt [$($branches:tt)*] $pat:pat = $fut:expr => $body:block $($rest:tt)*) => {
+        $crate::__select_parse!($biased [$($branches)* [[$pat] [$fut] [$body]]] $($rest)*)
+    };
+    ($biased:tt [$($branches:tt)*] $pat:pat = $fut:expr => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select_parse!($biased [$($branches)* [[$pat] [$fut] [$body]]] $($($rest)*)?)
+    };
+}
+
+// 给每个分支算出它的结果在嵌套 Either 中的位置：
+// 第 i 个分支的结果是 i 个 Right 包着一个 Left，最后一个分支不需要 Left，
+// 比如三个分支分别是 Left(a)、Right(Left(b))、Right(Right(c))
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_index {
+    ($biased:tt $else:tt [$($done:tt)*] [$($prefix:ident)*] [$($branch:tt)*]) => {
+        $crate::__select_expand!($biased $else $($done)* [[$($prefix)*] $($branch)*])
+    };
+    ($biased:tt $else:tt [$($done:tt)*] [$($prefix:ident)*] [$($branch:tt)*] $($rest:tt)+) => {
+        $crate::__select_index!(
+            $biased $else
+            [$($done)* [[$($prefix)* Left] $($branch)*]]
+            [$($prefix)* Right]
+            $($rest)+
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_wrap {
+    ([] [$($inner:tt)*]) => {
+        $($inner)*
+    };
+    ([$first:ident $($rest:ident)*] $inner:tt) => {
+        $crate::Either::$first($crate::__select_wrap!([$($rest)*] $inner))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_expand {
+    ($biased:tt [$else:expr] $([[$($path:ident)*] [$pat:pat] [$fut:expr] [$body:expr]])+) => {{
+        // 所有分支的结果类型都是同一个嵌套的 Either，所以可以放进同一个 Vec 交给 SelectAll
+        let mut branches: ::std::vec::Vec<
+            ::std::boxed::Box<dyn $crate::SimpleFuture<Output = _> + '_>,
+        > = ::std::vec![$(
+            ::std::boxed::Box::new($crate::SimpleFutureExt::map(
+                $crate::from_std($fut),
+                |output| $crate::__select_wrap!([$($path)*] [output]),
+            )),
+        )+];
+
+        // 每次都是新的 SelectAll，起始位置要记在每个调用处自己的计数器里，
+        // 否则 loop 中的 select! 总是先 poll 第一个分支，一直就绪的分支会饿死其他分支
+        static START: ::core::sync::atomic::AtomicUsize = ::core::sync::atomic::AtomicUsize::new(0);
+        let output = loop {
+            if branches.is_empty() {
+                break ::core::option::Option::None;
+            }
+            let mut select = $crate::select_all(branches);
+            if $biased {
+                select = select.biased();
+            } else {
+                select = select.start_at(START.fetch_add(1, ::core::sync::atomic::Ordering::Relaxed));
+            }
+            let (output, _, rest) = $crate::SimpleFutureExt::into_std(select).await;
+            branches = rest;
+            // 这里只检查是否匹配，绑定的变量用不到
+            #[allow(unreachable_patterns, unused_variables)]
+            let matched = match &output {
+                $($crate::__select_wrap!([$($path)*] [$pat]) => true,)+
+                _ => false,
+            };
+            if matched {
+                break ::core::option::Option::Some(output);
+            }
+        };
+        // 剩下的分支在这里被 drop 掉
+        ::core::mem::drop(branches);
+
+        match output {
+            $(::core::option::Option::Some($crate::__select_wrap!([$($path)*] [$pat])) => $body,)+
+            #[allow(unreachable_patterns)]
+            ::core::option::Option::Some(_) => ::core::unreachable!(),
+            ::core::option::Option::None => $else,
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_on;
+    use futures::channel::{mpsc, oneshot};
+    use futures::{SinkExt, StreamExt};
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        block_on(crate::from_std(future))
+    }
+
+    #[test]
+    fn join_and_try_join_flatten_results() {
+        run(async {
+            let (a,) = crate::join!(async { 1 });
+            let (b, c, d, e) = crate::join!(async { 2 }, async { "c" }, async { 'd' }, async {});
+            assert_eq!((a, b, c, d, e), (1, 2, "c", 'd', ()));
+
+            let ok: Result<_, &str> =
+                crate::try_join!(async { Ok(1) }, async { Ok(2) }, async { Ok(3) });
+            assert_eq!(ok, Ok((1, 2, 3)));
+
+            let (tx, never) = oneshot::channel::<()>();
+            let err = crate::try_join!(async { never.await.map_err(|_| "cancelled") }, async {
+                Err::<(), _>("boom")
+            });
+            assert_eq!(err, Err("boom"));
+            // 失败时还在等待的 Future 已经被 drop 掉了
+            assert!(tx.is_canceled());
+        });
+    }
+
+    #[test]
+    fn select_runs_matching_branch_and_supports_control_flow() {
+        run(async {
+            let (mut tx, mut rx) = mpsc::channel::<u32>(4);
+            for n in 1..=3 {
+                tx.send(n).await.unwrap();
+            }
+            drop(tx);
+
+            // rx.next() 最后返回 None，不匹配 Some(n)，这个分支被禁用，然后执行 else
+            let mut sum = 0;
+            loop {
+                crate::select! {
+                    Some(n) = rx.next() => sum += n,
+                    else => break,
+                }
+            }
+            assert_eq!(sum, 6);
+        });
+    }
+
+    #[test]
+    fn select_in_a_loop_does_not_starve_ready_branches() {
+        let wins = run(async {
+            let mut wins = [0; 2];
+            for _ in 0..10 {
+                crate::select! {
+                    a = async { 0 } => wins[a] += 1,
+                    b = async { 1 } => wins[b] += 1,
+                }
+            }
+            wins
+        });
+        // 两个分支每次都是就绪的，轮流获胜
+        assert_eq!(wins, [5, 5]);
+    }
+
+    #[test]
+    fn select_biased_prefers_first_ready_branch() {
+        let picked = run(async {
+            crate::select! {
+                biased;
+                a = async { 'a' } => a,
+                b = async { 'b' } => { b }
+            }
+        });
+        assert_eq!(picked, 'a');
+
+        // 不匹配的分支被禁用，继续等待其他分支
+        let picked = run(async {
+            let (tx, rx) = oneshot::channel();
+            std::thread::spawn(move || tx.send(7).unwrap());
+            crate::select! {
+                biased;
+                Some(n) = async { None::<u32> } => n,
+                Ok(n) = rx => n * 10,
+            }
+        });
+        assert_eq!(picked, 70);
+    }
+}
@@ -0,0 +1,243 @@
+use std::task::Waker;
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    Select 同时等待两个 Future，哪个先完成就返回哪个的结果，
+    另一个还没完成的 Future 会原样交还给调用者：
+    调用者可以继续等待它，也可以直接 drop 掉它（相当于取消）
+
+    这就是超时（和定时器 select）、取消（和取消信号 select）等模式的基础
+
+    轮询顺序：
+    1. biased：总是先 poll a，两个同时就绪时 a 优先
+    2. fair（默认）：每次 poll 轮流交换先后顺序，避免一方总是就绪时另一方永远得不到机会
+
+    std 的 Future 可以先用 `from_std` 转换，select 完之后再用 `into_std` 去 `.await`
+*/
+
+/// 两种可能中的一种
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+pub struct Select<FutureA, FutureB> {
+    inner: Option<(FutureA, FutureB)>,
+    biased: bool,
+
+    /// fair 模式下，下次是否先 poll b
+    b_first: bool,
+}
+
+/// 等待 a、b 中先完成的那个，公平地轮流先 poll
+pub fn select<A: SimpleFuture, B: SimpleFuture>(a: A, b: B) -> Select<A, B> {
+    Select {
+        inner: Some((a, b)),
+        biased: false,
+        b_first: false,
+    }
+}
+
+/// 等待 a、b 中先完成的那个，总是先 poll a
+pub fn select_biased<A: SimpleFuture, B: SimpleFuture>(a: A, b: B) -> Select<A, B> {
+    Select {
+        inner: Some((a, b)),
+        biased: true,
+        b_first: false,
+    }
+}
+
+impl<FutureA, FutureB> SimpleFuture for Select<FutureA, FutureB>
+where
+    FutureA: SimpleFuture,
+    FutureB: SimpleFuture,
+{
+    // 先完成的一方的结果，以及另一个没完成的 Future
+    type Output = Either<(FutureA::Output, FutureB), (FutureB::Output, FutureA)>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let (a, b) = self.inner.as_mut().expect("Select polled after completion");
+
+        let b_first = !self.biased && self.b_first;
+        self.b_first = !self.b_first;
+
+        let winner = if b_first {
+            match b.poll(waker) {
+                Poll::Ready(output) => Some(Either::Right(output)),
+                Poll::Pending => a.poll(waker).map(Either::Left).into_option(),
+            }
+        } else {
+            match a.poll(waker) {
+                Poll::Ready(output) => Some(Either::Left(output)),
+                Poll::Pending => b.poll(waker).map(Either::Right).into_option(),
+            }
+        };
+
+        match winner {
+            Some(Either::Left(output)) => {
+                let (_, b) = self.inner.take().unwrap();
+                Poll::Ready(Either::Left((output, b)))
+            }
+            Some(Either::Right(output)) => {
+                let (a, _) = self.inner.take().unwrap();
+                Poll::Ready(Either::Right((output, a)))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/*
+    SelectAll 等待一组同类型 Future 中最先完成的那个，
+    返回 (结果, 它在 Vec 中的下标, 剩下的 Future)
+*/
+pub struct SelectAll<F> {
+    inner: Vec<F>,
+    biased: bool,
+
+    /// fair 模式下，下次从哪个下标开始 poll
+    start: usize,
+}
+
+/// 等待一组 Future 中最先完成的那个，每次 poll 轮换起始位置
+///
+/// 传入空的集合会 panic，因为永远不会有结果
+pub fn select_all<I>(futures: I) -> SelectAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: SimpleFuture,
+{
+    let inner: Vec<_> = futures.into_iter().collect();
+    assert!(!inner.is_empty(), "select_all requires at least one future");
+    SelectAll {
+        inner,
+        biased: false,
+        start: 0,
+    }
+}
+
+impl<F> SelectAll<F> {
+    /// 总是按下标顺序 poll，下标小的优先
+    pub fn biased(mut self) -> Self {
+        self.biased = true;
+        self
+    }
+
+    /// fair 模式下第一次 poll 从下标 `start`（对长度取模）开始，
+    /// 每次都重新创建 SelectAll 的调用者（比如 select!）用它来轮换起始位置
+    pub fn start_at(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+}
+
+impl<F: SimpleFuture> SimpleFuture for SelectAll<F> {
+    type Output = (F::Output, usize, Vec<F>);
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        // 创建时不能为空，空了说明已经返回过结果，剩下的 Future 都交给了调用者
+        assert!(!self.inner.is_empty(), "SelectAll polled after completion");
+        let len = self.inner.len();
+        let start = if self.biased { 0 } else { self.start % len };
+        self.start = start + 1;
+
+        for offset in 0..len {
+            let index = (start + offset) % len;
+            if let Poll::Ready(output) = self.inner[index].poll(waker) {
+                let mut rest = std::mem::take(&mut self.inner);
+                rest.remove(index);
+                return Poll::Ready((output, index, rest));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    // 第 n 次 poll 时完成，n 为 usize::MAX 时相当于永远 Pending
+    struct ReadyAfter<T> {
+        polls_left: usize,
+        value: Option<T>,
+    }
+
+    fn ready_after<T>(polls_left: usize, value: T) -> ReadyAfter<T> {
+        ReadyAfter {
+            polls_left,
+            value: Some(value),
+        }
+    }
+
+    impl<T> SimpleFuture for ReadyAfter<T> {
+        type Output = T;
+
+        fn poll(&mut self, _waker: &Waker) -> Poll<T> {
+            self.polls_left -= 1;
+            if self.polls_left == 0 {
+                Poll::Ready(self.value.take().unwrap())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn select_hands_back_the_loser() {
+        let waker = noop_waker();
+        let mut fut = select(ready_after(usize::MAX, 'a'), ready_after(1, 'b'));
+        match fut.poll(&waker) {
+            Poll::Ready(Either::Right(('b', mut loser))) => {
+                assert!(loser.poll(&waker).is_pending());
+            }
+            _ => panic!("ready side should win"),
+        }
+    }
+
+    #[test]
+    fn fair_select_takes_turns_but_biased_does_not() {
+        let waker = noop_waker();
+
+        // 第二次 poll 时两边同时就绪：fair 模式这次先 poll b，biased 模式总是先 poll a
+        let mut fair = select(ready_after(2, 'a'), ready_after(2, 'b'));
+        assert!(fair.poll(&waker).is_pending());
+        assert!(matches!(
+            fair.poll(&waker),
+            Poll::Ready(Either::Right(('b', _)))
+        ));
+
+        let mut biased = select_biased(ready_after(2, 'a'), ready_after(2, 'b'));
+        assert!(biased.poll(&waker).is_pending());
+        assert!(matches!(
+            biased.poll(&waker),
+            Poll::Ready(Either::Left(('a', _)))
+        ));
+    }
+
+    #[test]
+    fn select_all_returns_index_and_remaining() {
+        let waker = noop_waker();
+        let futures = vec![
+            ready_after(usize::MAX, 0),
+            ready_after(1, 9),
+            ready_after(usize::MAX, 0),
+        ];
+        match select_all(futures).poll(&waker) {
+            Poll::Ready((9, 1, rest)) => assert_eq!(rest.len(), 2),
+            _ => panic!("second future should win"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "SelectAll polled after completion")]
+    fn select_all_panics_when_polled_after_completion() {
+        let waker = noop_waker();
+        let mut fut = select_all(vec![ready_after(1, ())]);
+        assert!(fut.poll(&waker).is_ready());
+        let _ = fut.poll(&waker);
+    }
+}
@@ -0,0 +1,138 @@
+use std::task::Waker;
+
+use crate::and_then::{AndThenFut, Map};
+use crate::compat::IntoStdFuture;
+
+//  Future 的一个简单实现
+pub enum Poll<T> {
+    Ready(T),
+    Pending,
+}
+
+pub trait SimpleFuture {
+    // Output 即未来要返回的值的类型
+    type Output;
+
+    // poll 类似轮询，调用 poll 方法就会驱动 SimpleFuture 向着完成继续前进;
+    // 参数 waker 是当前任务的唤醒句柄:
+    // 最早这里是一个函数指针 `wake: fn()`，但函数指针不能携带任何数据，
+    // 调用它时根本不知道该唤醒哪个任务，所以换成了标准库的 `Waker`，
+    // 它内部带有任务的信息，clone 之后可以保存起来，在未来有进展时调用 `wake()`
+    //
+    // 返回值 Poll：
+    // 1 如果是 Ready 就说明这个 Future 结束了，并且 Ready 的值的类型就是 Output 类型
+    // 2 如果是 Pending 就说明这个 Future 还没有结束，未来至少还有 poll 一次，看看到时候进展
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output>;
+}
+
+/*
+    真正的 Future Trait:
+
+    trait Future {
+        type Output;
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output>;
+    }
+
+    和 SimpleFuture 的区别：
+    1. self 是 `Pin<&mut Self>`，这样 async 块生成的自引用的 Future 也能安全地被 poll（见 pin_04）
+    2. 参数是 `Context`，目前它只是对 `&Waker` 的一层包装，`cx.waker()` 就能拿到 Waker
+
+    所以两者可以互相转换，见 compat 模块
+*/
+
+impl<T> Poll<T> {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Poll::Ready(_))
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Poll::Pending)
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Poll<U> {
+        match self {
+            Poll::Ready(t) => Poll::Ready(f(t)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    pub(crate) fn into_option(self) -> Option<T> {
+        match self {
+            Poll::Ready(t) => Some(t),
+            Poll::Pending => None,
+        }
+    }
+}
+
+// Box 中的 SimpleFuture 也是 SimpleFuture，这样不同类型的 Future 可以放进同一个 Vec<Box<dyn SimpleFuture>>
+impl<F: SimpleFuture + ?Sized> SimpleFuture for Box<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        (**self).poll(waker)
+    }
+}
+
+impl<T> From<std::task::Poll<T>> for Poll<T> {
+    fn from(poll: std::task::Poll<T>) -> Self {
+        match poll {
+            std::task::Poll::Ready(t) => Poll::Ready(t),
+            std::task::Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> From<Poll<T>> for std::task::Poll<T> {
+    fn from(poll: Poll<T>) -> Self {
+        match poll {
+            Poll::Ready(t) => std::task::Poll::Ready(t),
+            Poll::Pending => std::task::Poll::Pending,
+        }
+    }
+}
+
+/*
+    Ready 是一个立即完成的 Future，第一次 poll 就返回 Ready(value)，
+    常用在 then、and_then 的闭包中直接返回一个值
+*/
+pub struct Ready<T>(Option<T>);
+
+/// 创建一个立即完成的 Future
+pub fn ready<T>(value: T) -> Ready<T> {
+    Ready(Some(value))
+}
+
+impl<T> SimpleFuture for Ready<T> {
+    type Output = T;
+
+    fn poll(&mut self, _waker: &Waker) -> Poll<T> {
+        Poll::Ready(self.0.take().expect("Ready polled after completion"))
+    }
+}
+
+/// SimpleFuture 的扩展方法
+pub trait SimpleFutureExt: SimpleFuture + Sized {
+    /// 完成后把结果交给 f，再运行 f 返回的 Future
+    fn then<Fut, F>(self, f: F) -> AndThenFut<Self, Fut, F>
+    where
+        F: FnOnce(Self::Output) -> Fut,
+        Fut: SimpleFuture,
+    {
+        AndThenFut::new(self, f)
+    }
+
+    /// 完成后用 f 转换结果
+    fn map<F, T>(self, f: F) -> Map<Self, F>
+    where
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map::new(self, f)
+    }
+
+    /// 转换成 std 的 Future
+    fn into_std(self) -> IntoStdFuture<Self> {
+        IntoStdFuture::new(self)
+    }
+}
+
+impl<F: SimpleFuture> SimpleFutureExt for F {}
@@ -0,0 +1,173 @@
+use std::{
+    convert::Infallible,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Waker},
+};
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    SimpleSink 是可以异步地往里面写数据的东西（比如 channel 的发送端、socket），
+    和 SimpleStream 正好相反
+
+    写一个数据分三步：
+    1. poll_ready：等待 sink 准备好接收下一个数据（比如缓冲区有空位了）
+    2. start_send：把数据交给 sink，只有 poll_ready 返回 Ready(Ok(())) 之后才能调用
+    3. poll_flush：等待之前交给 sink 的数据都真正地写出去
+
+    不再写数据时调用 poll_close，它会先 flush，再关闭 sink
+*/
+pub trait SimpleSink<Item> {
+    type Error;
+
+    fn poll_ready(&mut self, waker: &Waker) -> Poll<Result<(), Self::Error>>;
+
+    fn start_send(&mut self, item: Item) -> Result<(), Self::Error>;
+
+    fn poll_flush(&mut self, waker: &Waker) -> Poll<Result<(), Self::Error>>;
+
+    fn poll_close(&mut self, waker: &Waker) -> Poll<Result<(), Self::Error>>;
+}
+
+/// SimpleSink 的扩展方法
+pub trait SimpleSinkExt<Item>: SimpleSink<Item> {
+    /// 写入一个数据并 flush
+    fn send(&mut self, item: Item) -> Send<'_, Self, Item> {
+        Send {
+            sink: self,
+            item: Some(item),
+        }
+    }
+
+    /// flush 之后关闭
+    fn close(&mut self) -> Close<'_, Self, Item> {
+        Close {
+            sink: self,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<Item, S: SimpleSink<Item> + ?Sized> SimpleSinkExt<Item> for S {}
+
+/// `SimpleSinkExt::send` 返回的 Future
+pub struct Send<'a, S: ?Sized, Item> {
+    sink: &'a mut S,
+    item: Option<Item>,
+}
+
+impl<S: SimpleSink<Item> + ?Sized, Item> SimpleFuture for Send<'_, S, Item> {
+    type Output = Result<(), S::Error>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        if self.item.is_some() {
+            match self.sink.poll_ready(waker) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+            let item = self.item.take().unwrap();
+            if let Err(err) = self.sink.start_send(item) {
+                return Poll::Ready(Err(err));
+            }
+        }
+        self.sink.poll_flush(waker)
+    }
+}
+
+/// `SimpleSinkExt::close` 返回的 Future
+pub struct Close<'a, S: ?Sized, Item> {
+    sink: &'a mut S,
+    _item: PhantomData<fn(Item)>,
+}
+
+impl<S: SimpleSink<Item> + ?Sized, Item> SimpleFuture for Close<'_, S, Item> {
+    type Output = Result<(), S::Error>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.sink.poll_close(waker)
+    }
+}
+
+// Vec 可以直接当作 sink，数据都追加到末尾，永远不会出错
+impl<T> SimpleSink<T> for Vec<T> {
+    type Error = Infallible;
+
+    fn poll_ready(&mut self, _waker: &Waker) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(&mut self, item: T) -> Result<(), Infallible> {
+        self.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(&mut self, _waker: &Waker) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(&mut self, _waker: &Waker) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// sink 的可变引用也是 sink，这样 forward 之后还能继续使用原来的 sink
+impl<S: SimpleSink<Item> + ?Sized, Item> SimpleSink<Item> for &mut S {
+    type Error = S::Error;
+
+    fn poll_ready(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        (**self).poll_ready(waker)
+    }
+
+    fn start_send(&mut self, item: Item) -> Result<(), S::Error> {
+        (**self).start_send(item)
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        (**self).poll_flush(waker)
+    }
+
+    fn poll_close(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        (**self).poll_close(waker)
+    }
+}
+
+/// 当作 SimpleSink 使用的 `futures::Sink`
+///
+/// 和 FromStdFuture 一样，先 Box::pin 到堆上
+pub struct FromStdSink<S>(Pin<Box<S>>);
+
+/// 把 `futures::Sink` 转换成 SimpleSink
+pub fn from_std_sink<S>(sink: S) -> FromStdSink<S> {
+    FromStdSink(Box::pin(sink))
+}
+
+impl<S: futures::Sink<Item>, Item> SimpleSink<Item> for FromStdSink<S> {
+    type Error = S::Error;
+
+    fn poll_ready(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        self.0
+            .as_mut()
+            .poll_ready(&mut Context::from_waker(waker))
+            .into()
+    }
+
+    fn start_send(&mut self, item: Item) -> Result<(), S::Error> {
+        self.0.as_mut().start_send(item)
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        self.0
+            .as_mut()
+            .poll_flush(&mut Context::from_waker(waker))
+            .into()
+    }
+
+    fn poll_close(&mut self, waker: &Waker) -> Poll<Result<(), S::Error>> {
+        self.0
+            .as_mut()
+            .poll_close(&mut Context::from_waker(waker))
+            .into()
+    }
+}
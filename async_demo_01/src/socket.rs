@@ -0,0 +1,239 @@
+/*
+    一个真正可用的非阻塞 Socket，以及例子一中的 SocketRead
+
+    Socket 包装了一个非阻塞的 TcpStream：
+    1. has_data_to_read：现在读会不会阻塞（有数据、对方已关闭或出错都算“不会阻塞”）
+    2. read_buf：把当前能读到的数据全部读出来
+    3. set_readable_callback：登记 waker，socket 可读时由 Reactor 线程调用 waker.wake()
+
+    Reactor 用的是 timer_future_02 中的那一个（`timer_future_02::reactor::Async`）：
+    一个后台线程借助 epoll/kqueue 等待所有注册过的 fd，每个事件只通知一次，下次有人等待时再重新打开兴趣。
+    重新打开兴趣时 epoll 会检查 fd 当前的状态，所以在 has_data_to_read 返回 false
+    和 set_readable_callback 之间到达的数据不会被错过。Socket 被 drop 时 Async 先注销 fd 再关闭它
+*/
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    task::Waker,
+};
+
+use timer_future_02::reactor::Async;
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/// 注册到 Reactor 中的非阻塞 TCP 连接
+pub struct Socket {
+    inner: Async<TcpStream>,
+}
+
+impl Socket {
+    /// 连接到 `addr`，连接建立之后切换到非阻塞模式
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Socket> {
+        Socket::from_std(TcpStream::connect(addr)?)
+    }
+
+    pub fn from_std(stream: TcpStream) -> io::Result<Socket> {
+        stream.set_nonblocking(true)?;
+        Ok(Socket {
+            inner: Async::new(stream)?,
+        })
+    }
+
+    /// 现在读是否不会阻塞
+    ///
+    /// 对方关闭了连接或者出错时也返回 true，这时 `read_buf` 会返回空的数据或者错误
+    pub fn has_data_to_read(&self) -> bool {
+        let result = self.inner.get_ref().peek(&mut [0; 1]);
+        !matches!(result, Err(err) if err.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    /// 读出当前所有能读到的数据，对方关闭了连接时返回空的 Vec
+    pub fn read_buf(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            match self.inner.get_ref().read(&mut buf) {
+                Ok(0) => return Ok(data),
+                Ok(n) => data.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(data),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// 尽量写出 `buf`，返回写出的字节数，写缓冲区满时返回 `WouldBlock`
+    pub fn write_buf(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.get_ref().write(buf)
+    }
+
+    /// socket 可读时调用 `waker.wake()`，只通知一次
+    pub fn set_readable_callback(&self, waker: Waker) {
+        // 登记失败时直接唤醒，下次 poll 时读写会把错误返回给调用者
+        if self.inner.register_readable(&waker).is_err() {
+            waker.wake();
+        }
+    }
+
+    /// socket 可写时调用 `waker.wake()`，只通知一次
+    pub fn set_writable_callback(&self, waker: Waker) {
+        if self.inner.register_writable(&waker).is_err() {
+            waker.wake();
+        }
+    }
+
+    /// 关闭写方向，对方会读到 EOF
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.inner.get_ref().shutdown(Shutdown::Write)
+    }
+
+    /// 读出一次数据
+    pub fn read(&self) -> SocketRead<'_> {
+        SocketRead { socket: self }
+    }
+
+    /// 把 `data` 全部写出
+    pub fn write_all(&self, data: impl Into<Vec<u8>>) -> SocketWrite<'_> {
+        SocketWrite {
+            socket: self,
+            data: data.into(),
+            written: 0,
+        }
+    }
+}
+
+// 例子一
+pub struct SocketRead<'a> {
+    socket: &'a Socket,
+}
+
+impl SimpleFuture for SocketRead<'_> {
+    type Output = io::Result<Vec<u8>>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        if self.socket.has_data_to_read() {
+            // socket 有数据，读取数据到 buffer 并返回
+            Poll::Ready(self.socket.read_buf())
+        } else {
+            /*
+                socket 还没有数据时，在未来有数据时，或这个 future 准备取得更多进展时候，
+                带告诉它，就是通过 waker 告诉，当未来有数据时候，waker.wake() 就会被调用
+                wake 被调用后就会再次调用 poll 这个方法来检查数据是否真的有了
+                最后返回 Pending 变体
+            */
+            self.socket.set_readable_callback(waker.clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// 把数据全部写出的 Future，写缓冲区满时等待 socket 可写
+pub struct SocketWrite<'a> {
+    socket: &'a Socket,
+    data: Vec<u8>,
+    written: usize,
+}
+
+impl SimpleFuture for SocketWrite<'_> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        while self.written < self.data.len() {
+            match self.socket.write_buf(&self.data[self.written..]) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(n) => self.written += n,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.socket.set_writable_callback(waker.clone());
+                    return Poll::Pending;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{join, SimpleFutureExt, TryFutureExt};
+    use futures::executor::block_on;
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    // 在后台线程中运行一个回显服务器，每个连接把收到的数据原样发回去
+    fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut reader = stream.try_clone().unwrap();
+                    let _ = io::copy(&mut reader, &mut stream);
+                });
+            }
+        });
+        addr
+    }
+
+    // 一直读到对方关闭连接
+    fn read_to_end(socket: &Socket) -> impl SimpleFuture<Output = io::Result<Vec<u8>>> + '_ {
+        crate::from_std(async move {
+            let mut data = Vec::new();
+            loop {
+                let chunk = socket.read().into_std().await?;
+                if chunk.is_empty() {
+                    return Ok(data);
+                }
+                data.extend(chunk);
+            }
+        })
+    }
+
+    #[test]
+    fn write_then_read_echo() {
+        let socket = Socket::connect(echo_server()).unwrap();
+        let echoed = socket
+            .write_all("hello")
+            .and_then(|()| crate::ready(socket.shutdown_write()))
+            .and_then(|()| read_to_end(&socket));
+        assert_eq!(block_on(echoed.into_std()).unwrap(), b"hello");
+    }
+
+    // 写完之后关闭写方向，同时一直读到对方关闭连接
+    fn round_trip(
+        socket: &Socket,
+        data: Vec<u8>,
+    ) -> impl SimpleFuture<Output = (io::Result<()>, io::Result<Vec<u8>>)> + '_ {
+        join(
+            socket
+                .write_all(data)
+                .and_then(move |()| crate::ready(socket.shutdown_write())),
+            read_to_end(socket),
+        )
+    }
+
+    #[test]
+    fn join_two_sockets_and_large_write() {
+        let addr = echo_server();
+        let a = Socket::connect(addr).unwrap();
+        let b = Socket::connect(addr).unwrap();
+        // 比 socket 缓冲区大得多，写的时候一定会遇到 WouldBlock，所以要边写边读
+        let big = vec![7u8; 4 << 20];
+
+        let ((wa, ra), (wb, rb)) = block_on(
+            join(
+                round_trip(&a, big.clone()),
+                round_trip(&b, b"small".to_vec()),
+            )
+            .into_std(),
+        );
+        wa.unwrap();
+        wb.unwrap();
+        assert_eq!(ra.unwrap(), big);
+        assert_eq!(rb.unwrap(), b"small");
+    }
+}
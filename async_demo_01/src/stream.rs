@@ -0,0 +1,432 @@
+//! SimpleStream 以及它的组合器
+//!
+//! 组合器的类型名（Map、Filter 等）和 Future 的组合器重名，所以放在单独的 `stream` 模块中，
+//! trait 和常用的函数在 crate 根上也可以直接使用
+
+use std::{
+    pin::Pin,
+    task::{Context, Waker},
+};
+
+use crate::futures_unordered::FuturesUnordered;
+use crate::simple_future::{Poll, SimpleFuture};
+use crate::sink::SimpleSink;
+
+/*
+    SimpleFuture 只会产生一个值，SimpleStream 会陆续产生多个值，就像异步版的 Iterator
+
+    poll_next 的返回值：
+    1. Ready(Some(item))：产生了一个值，之后还可以继续 poll
+    2. Ready(None)：结束了，之后不应该再 poll
+    3. Pending：暂时还没有值，和 SimpleFuture 一样，有进展时 waker 会被调用
+*/
+pub trait SimpleStream {
+    type Item;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<Self::Item>>;
+}
+
+/// SimpleStream 的扩展方法
+pub trait SimpleStreamExt: SimpleStream + Sized {
+    /// 等待下一个值
+    fn next(&mut self) -> Next<'_, Self> {
+        Next { stream: self }
+    }
+
+    /// 用 f 转换每一个值
+    fn map<F, T>(self, f: F) -> Map<Self, F>
+    where
+        F: FnMut(Self::Item) -> T,
+    {
+        Map { stream: self, f }
+    }
+
+    /// 只保留 f 返回 true 的值
+    fn filter<F>(self, f: F) -> Filter<Self, F>
+    where
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter { stream: self, f }
+    }
+
+    /// 最多产生 n 个值
+    fn take(self, n: usize) -> Take<Self> {
+        Take {
+            stream: self,
+            remaining: n,
+        }
+    }
+
+    /// 每个值都是一个 Future，同时最多运行 n 个，按完成的顺序产生它们的结果
+    fn buffer_unordered(self, n: usize) -> BufferUnordered<Self>
+    where
+        Self::Item: SimpleFuture,
+    {
+        assert!(n > 0, "buffer_unordered requires a limit of at least one");
+        BufferUnordered {
+            stream: Some(self),
+            in_progress: FuturesUnordered::new(),
+            limit: n,
+        }
+    }
+
+    /// 把所有的值写进 sink，结束后关闭 sink
+    fn forward<Si>(self, sink: Si) -> Forward<Self, Si>
+    where
+        Si: SimpleSink<Self::Item>,
+    {
+        Forward {
+            stream: Some(self),
+            sink,
+            buffered: None,
+        }
+    }
+
+    /// 从 init 开始，用 f 把所有的值合并成一个
+    fn fold<Acc, F>(self, init: Acc, f: F) -> Fold<Self, F, Acc>
+    where
+        F: FnMut(Acc, Self::Item) -> Acc,
+    {
+        Fold {
+            stream: self,
+            f,
+            acc: Some(init),
+        }
+    }
+
+    /// 转换成 `futures::Stream`
+    fn into_std_stream(self) -> IntoStdStream<Self> {
+        IntoStdStream(self)
+    }
+}
+
+impl<S: SimpleStream> SimpleStreamExt for S {}
+
+/// 依次产生迭代器中的值的 SimpleStream
+pub struct Iter<I>(I);
+
+pub fn iter<I: IntoIterator>(items: I) -> Iter<I::IntoIter> {
+    Iter(items.into_iter())
+}
+
+impl<I: Iterator> SimpleStream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(&mut self, _waker: &Waker) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.0.next())
+    }
+}
+
+impl<F: SimpleFuture> SimpleStream for FuturesUnordered<F> {
+    type Item = F::Output;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<F::Output>> {
+        FuturesUnordered::poll_next(self, waker)
+    }
+}
+
+/// `SimpleStreamExt::next` 返回的 Future
+pub struct Next<'a, S> {
+    stream: &'a mut S,
+}
+
+impl<S: SimpleStream> SimpleFuture for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.stream.poll_next(waker)
+    }
+}
+
+/// `SimpleStreamExt::map` 返回的 Stream
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F, T> SimpleStream for Map<S, F>
+where
+    S: SimpleStream,
+    F: FnMut(S::Item) -> T,
+{
+    type Item = T;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<T>> {
+        let f = &mut self.f;
+        self.stream.poll_next(waker).map(|item| item.map(f))
+    }
+}
+
+/// `SimpleStreamExt::filter` 返回的 Stream
+pub struct Filter<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F> SimpleStream for Filter<S, F>
+where
+    S: SimpleStream,
+    F: FnMut(&S::Item) -> bool,
+{
+    type Item = S::Item;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<S::Item>> {
+        // 被过滤掉的值不会唤醒任何人，所以要一直 poll 到拿到一个留下的值或者 Pending 为止
+        loop {
+            match self.stream.poll_next(waker) {
+                Poll::Ready(Some(item)) if !(self.f)(&item) => continue,
+                poll => return poll,
+            }
+        }
+    }
+}
+
+/// `SimpleStreamExt::take` 返回的 Stream
+pub struct Take<S> {
+    stream: S,
+    remaining: usize,
+}
+
+impl<S: SimpleStream> SimpleStream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<S::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let poll = self.stream.poll_next(waker);
+        match &poll {
+            Poll::Ready(Some(_)) => self.remaining -= 1,
+            Poll::Ready(None) => self.remaining = 0,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+/*
+    BufferUnordered 从 stream 中取出 Future，放进 FuturesUnordered 中一起运行，
+    同时运行的个数不超过 limit，满了就先不从 stream 中取了（背压）
+*/
+pub struct BufferUnordered<S: SimpleStream> {
+    /// stream 结束后变成 None
+    stream: Option<S>,
+    in_progress: FuturesUnordered<S::Item>,
+    limit: usize,
+}
+
+impl<S> SimpleStream for BufferUnordered<S>
+where
+    S: SimpleStream,
+    S::Item: SimpleFuture,
+{
+    type Item = <S::Item as SimpleFuture>::Output;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<Self::Item>> {
+        // 先把空位填满
+        while self.in_progress.len() < self.limit {
+            let Some(stream) = self.stream.as_mut() else {
+                break;
+            };
+            match stream.poll_next(waker) {
+                Poll::Ready(Some(future)) => self.in_progress.push(future),
+                Poll::Ready(None) => self.stream = None,
+                Poll::Pending => break,
+            }
+        }
+
+        match self.in_progress.poll_next(waker) {
+            Poll::Ready(Some(output)) => Poll::Ready(Some(output)),
+            // 没有正在运行的 Future 了，stream 也结束了才算结束
+            Poll::Ready(None) if self.stream.is_none() => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/*
+    Forward 把 stream 中的值一个一个地写进 sink：
+    1. 从 stream 中取出一个值，先暂存在 buffered 中
+    2. 等 sink 的 poll_ready 就绪后再 start_send
+    3. stream 暂时没有值时 flush 一下，让已经写进去的值尽快发出去
+    4. stream 结束后 close sink
+*/
+pub struct Forward<S: SimpleStream, Si> {
+    /// stream 结束后变成 None
+    stream: Option<S>,
+    sink: Si,
+    buffered: Option<S::Item>,
+}
+
+impl<S, Si> SimpleFuture for Forward<S, Si>
+where
+    S: SimpleStream,
+    Si: SimpleSink<S::Item>,
+{
+    type Output = Result<(), Si::Error>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        loop {
+            if self.buffered.is_some() {
+                match self.sink.poll_ready(waker) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                }
+                let item = self.buffered.take().unwrap();
+                if let Err(err) = self.sink.start_send(item) {
+                    return Poll::Ready(Err(err));
+                }
+            }
+
+            let Some(stream) = self.stream.as_mut() else {
+                return self.sink.poll_close(waker);
+            };
+            match stream.poll_next(waker) {
+                Poll::Ready(Some(item)) => self.buffered = Some(item),
+                Poll::Ready(None) => self.stream = None,
+                Poll::Pending => {
+                    return match self.sink.poll_flush(waker) {
+                        Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                        _ => Poll::Pending,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// `SimpleStreamExt::fold` 返回的 Future
+pub struct Fold<S, F, Acc> {
+    stream: S,
+    f: F,
+    acc: Option<Acc>,
+}
+
+impl<S, F, Acc> SimpleFuture for Fold<S, F, Acc>
+where
+    S: SimpleStream,
+    F: FnMut(Acc, S::Item) -> Acc,
+{
+    type Output = Acc;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Acc> {
+        loop {
+            match self.stream.poll_next(waker) {
+                Poll::Ready(Some(item)) => {
+                    let acc = self.acc.take().expect("Fold polled after completion");
+                    self.acc = Some((self.f)(acc, item));
+                }
+                Poll::Ready(None) => {
+                    return Poll::Ready(self.acc.take().expect("Fold polled after completion"))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// 可以当作 `futures::Stream` 使用的 SimpleStream
+pub struct IntoStdStream<S>(S);
+
+// 和 IntoStdFuture 一样，SimpleStream 本来就可以移动
+impl<S> Unpin for IntoStdStream<S> {}
+
+impl<S: SimpleStream> futures::Stream for IntoStdStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> std::task::Poll<Option<S::Item>> {
+        self.get_mut().0.poll_next(cx.waker()).into()
+    }
+}
+
+/// 当作 SimpleStream 使用的 `futures::Stream`
+pub struct FromStdStream<S>(Pin<Box<S>>);
+
+/// 把 `futures::Stream` 转换成 SimpleStream
+pub fn from_std_stream<S: futures::Stream>(stream: S) -> FromStdStream<S> {
+    FromStdStream(Box::pin(stream))
+}
+
+impl<S: futures::Stream> SimpleStream for FromStdStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(&mut self, waker: &Waker) -> Poll<Option<S::Item>> {
+        self.0
+            .as_mut()
+            .poll_next(&mut Context::from_waker(waker))
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_std, sink::from_std_sink, SimpleFutureExt};
+    use futures::{channel::mpsc, executor::block_on, StreamExt};
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn map_filter_take_fold() {
+        let sum = iter(1..)
+            .map(|n| n * n)
+            .filter(|n| n % 2 == 1)
+            .take(3)
+            .fold(0, |acc, n| acc + n);
+        // 1 + 9 + 25
+        assert_eq!(block_on(sum.into_std()), 35);
+    }
+
+    // 让出一次：先返回 Pending 并立刻唤醒自己，下次 poll 时完成
+    async fn yield_now() {
+        let mut yielded = false;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                std::task::Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn buffer_unordered_limits_concurrency() {
+        let running = Rc::new(Cell::new(0));
+        let max_running = Rc::new(Cell::new(0));
+
+        let jobs = iter(0..10).map(|n| {
+            let (running, max_running) = (running.clone(), max_running.clone());
+            from_std(async move {
+                running.set(running.get() + 1);
+                max_running.set(max_running.get().max(running.get()));
+                yield_now().await;
+                running.set(running.get() - 1);
+                n
+            })
+        });
+        let stream = jobs.buffer_unordered(3).into_std_stream();
+        let mut results = block_on(stream.collect::<Vec<_>>());
+
+        results.sort_unstable();
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+        assert_eq!(max_running.get(), 3);
+    }
+
+    #[test]
+    fn forward_into_vec_and_std_sink() {
+        let mut collected = Vec::new();
+        block_on(iter(1..=3).forward(&mut collected).into_std()).unwrap();
+        assert_eq!(collected, vec![1, 2, 3]);
+
+        // 写进 futures 的 channel，再把接收端当作 SimpleStream 读回来
+        let (tx, rx) = mpsc::channel(1);
+        let forward = iter(["a", "b", "c"]).forward(from_std_sink(tx));
+        let received = from_std_stream(rx).fold(String::new(), |acc, s| acc + s);
+        let (sent, received) = block_on(crate::join(forward, received).into_std());
+        sent.unwrap();
+        assert_eq!(received, "abc");
+    }
+}
@@ -0,0 +1,349 @@
+use std::{marker::PhantomData, task::Waker};
+
+use crate::simple_future::{Poll, SimpleFuture};
+
+/*
+    结果是 Result 的 Future 的组合器，和 `?` 的行为一致：
+
+    - and_then：成功时用 Ok 的值构建下一个 Future，失败时直接返回 Err，不会调用闭包
+    - or_else：失败时用 Err 的值构建下一个 Future（比如重试、降级），成功时直接返回 Ok
+    - map_ok / map_err：只转换 Ok 或 Err 的值
+    - err_into：用 From 把错误转换成另一种类型，和 `?` 做的转换一样，
+      这样不同错误类型的 Future 就可以放进同一个 try_join 中
+    - unwrap_or_else：失败时用闭包算出一个默认值，结果就不再是 Result 了
+
+    这样顺序执行的多个可能失败的步骤就可以像下面这样用组合器串起来：
+
+        connect(addr)
+            .and_then(|socket| send_request(socket))
+            .and_then(|socket| read_response(socket))
+            .map_err(MyError::Io)
+*/
+
+/// 结果是 Result 的 SimpleFuture，用关联类型拿到 Ok、Err 的类型，写泛型时方便一些
+pub trait TryFuture: SimpleFuture {
+    type Ok;
+    type Error;
+
+    fn try_poll(&mut self, waker: &Waker) -> Poll<Result<Self::Ok, Self::Error>>;
+}
+
+impl<F, T, E> TryFuture for F
+where
+    F: SimpleFuture<Output = Result<T, E>>,
+{
+    type Ok = T;
+    type Error = E;
+
+    fn try_poll(&mut self, waker: &Waker) -> Poll<Result<T, E>> {
+        self.poll(waker)
+    }
+}
+
+/// 结果是 Result 的 SimpleFuture 的扩展方法
+pub trait TryFutureExt<T, E>: SimpleFuture<Output = Result<T, E>> + Sized {
+    fn and_then<Fut, F, U>(self, f: F) -> TryAndThen<Self, Fut, F>
+    where
+        F: FnOnce(T) -> Fut,
+        Fut: SimpleFuture<Output = Result<U, E>>,
+    {
+        TryAndThen {
+            state: TryChain::First(self, f),
+        }
+    }
+
+    fn or_else<Fut, F, E2>(self, f: F) -> OrElse<Self, Fut, F>
+    where
+        F: FnOnce(E) -> Fut,
+        Fut: SimpleFuture<Output = Result<T, E2>>,
+    {
+        OrElse {
+            state: TryChain::First(self, f),
+        }
+    }
+
+    fn map_ok<F, U>(self, f: F) -> MapOk<Self, F>
+    where
+        F: FnOnce(T) -> U,
+    {
+        MapOk {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    fn map_err<F, E2>(self, f: F) -> MapErr<Self, F>
+    where
+        F: FnOnce(E) -> E2,
+    {
+        MapErr {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    fn err_into<E2>(self) -> ErrInto<Self, E2>
+    where
+        E: Into<E2>,
+    {
+        ErrInto {
+            future: self,
+            _error: PhantomData,
+        }
+    }
+
+    fn unwrap_or_else<F>(self, f: F) -> UnwrapOrElse<Self, F>
+    where
+        F: FnOnce(E) -> T,
+    {
+        UnwrapOrElse {
+            future: self,
+            f: Some(f),
+        }
+    }
+}
+
+impl<Fut, T, E> TryFutureExt<T, E> for Fut where Fut: SimpleFuture<Output = Result<T, E>> {}
+
+// and_then、or_else 共用的状态，和 AndThenFut 一样，只是第一步的结果可能直接就是最终结果
+enum TryChain<FutureA, FutureB, F> {
+    First(FutureA, F),
+    Second(FutureB),
+    Done,
+}
+
+impl<FutureA, FutureB, F> TryChain<FutureA, FutureB, F>
+where
+    FutureA: SimpleFuture,
+    FutureB: SimpleFuture,
+{
+    // `step` 决定 first 的结果是直接返回（Err），还是交给 f 构建第二个 Future（Ok）
+    fn poll<S>(&mut self, waker: &Waker, step: S) -> Poll<FutureB::Output>
+    where
+        S: FnOnce(FutureA::Output, F) -> Result<FutureB, FutureB::Output>,
+    {
+        if let TryChain::First(first, _) = self {
+            let output = match first.poll(waker) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return Poll::Pending,
+            };
+            let TryChain::First(_, f) = std::mem::replace(self, TryChain::Done) else {
+                unreachable!()
+            };
+            match step(output, f) {
+                Ok(second) => *self = TryChain::Second(second),
+                Err(output) => return Poll::Ready(output),
+            }
+        }
+
+        match self {
+            TryChain::Second(second) => {
+                let poll = second.poll(waker);
+                if poll.is_ready() {
+                    *self = TryChain::Done;
+                }
+                poll
+            }
+            _ => panic!("future polled after completion"),
+        }
+    }
+}
+
+/// `TryFutureExt::and_then` 返回的 Future
+pub struct TryAndThen<FutureA, FutureB, F> {
+    state: TryChain<FutureA, FutureB, F>,
+}
+
+impl<FutureA, FutureB, F, T, U, E> SimpleFuture for TryAndThen<FutureA, FutureB, F>
+where
+    FutureA: SimpleFuture<Output = Result<T, E>>,
+    FutureB: SimpleFuture<Output = Result<U, E>>,
+    F: FnOnce(T) -> FutureB,
+{
+    type Output = Result<U, E>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.state.poll(waker, |output, f| match output {
+            Ok(t) => Ok(f(t)),
+            Err(e) => Err(Err(e)),
+        })
+    }
+}
+
+/// `TryFutureExt::or_else` 返回的 Future
+pub struct OrElse<FutureA, FutureB, F> {
+    state: TryChain<FutureA, FutureB, F>,
+}
+
+impl<FutureA, FutureB, F, T, E, E2> SimpleFuture for OrElse<FutureA, FutureB, F>
+where
+    FutureA: SimpleFuture<Output = Result<T, E>>,
+    FutureB: SimpleFuture<Output = Result<T, E2>>,
+    F: FnOnce(E) -> FutureB,
+{
+    type Output = Result<T, E2>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.state.poll(waker, |output, f| match output {
+            Ok(t) => Err(Ok(t)),
+            Err(e) => Ok(f(e)),
+        })
+    }
+}
+
+/// `TryFutureExt::map_ok` 返回的 Future
+pub struct MapOk<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F, T, U, E> SimpleFuture for MapOk<Fut, F>
+where
+    Fut: SimpleFuture<Output = Result<T, E>>,
+    F: FnOnce(T) -> U,
+{
+    type Output = Result<U, E>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.future.poll(waker).map(|result| {
+            let f = self.f.take().expect("MapOk polled after completion");
+            result.map(f)
+        })
+    }
+}
+
+/// `TryFutureExt::map_err` 返回的 Future
+pub struct MapErr<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F, T, E, E2> SimpleFuture for MapErr<Fut, F>
+where
+    Fut: SimpleFuture<Output = Result<T, E>>,
+    F: FnOnce(E) -> E2,
+{
+    type Output = Result<T, E2>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.future.poll(waker).map(|result| {
+            let f = self.f.take().expect("MapErr polled after completion");
+            result.map_err(f)
+        })
+    }
+}
+
+/// `TryFutureExt::err_into` 返回的 Future
+pub struct ErrInto<Fut, E2> {
+    future: Fut,
+    _error: PhantomData<fn() -> E2>,
+}
+
+impl<Fut, T, E, E2> SimpleFuture for ErrInto<Fut, E2>
+where
+    Fut: SimpleFuture<Output = Result<T, E>>,
+    E: Into<E2>,
+{
+    type Output = Result<T, E2>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        self.future
+            .poll(waker)
+            .map(|result| result.map_err(Into::into))
+    }
+}
+
+/// `TryFutureExt::unwrap_or_else` 返回的 Future
+pub struct UnwrapOrElse<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F, T, E> SimpleFuture for UnwrapOrElse<Fut, F>
+where
+    Fut: SimpleFuture<Output = Result<T, E>>,
+    F: FnOnce(E) -> T,
+{
+    type Output = T;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<T> {
+        self.future.poll(waker).map(|result| {
+            let f = self.f.take().expect("UnwrapOrElse polled after completion");
+            result.unwrap_or_else(f)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ready, SimpleFutureExt};
+    use futures::task::noop_waker;
+
+    fn run<F: SimpleFuture>(mut future: F) -> F::Output {
+        let waker = noop_waker();
+        loop {
+            if let Poll::Ready(output) = future.poll(&waker) {
+                return output;
+            }
+        }
+    }
+
+    fn parse(s: &'static str) -> impl SimpleFuture<Output = Result<i32, String>> {
+        ready(s.parse::<i32>().map_err(|e| e.to_string()))
+    }
+
+    #[test]
+    fn and_then_passes_values_and_short_circuits() {
+        let ok = parse("20").and_then(|n| ready(Ok(n * 2))).map_ok(|n| n + 2);
+        assert_eq!(run(ok), Ok(42));
+
+        let mut called = false;
+        let err = parse("x").and_then(|n| {
+            called = true;
+            ready(Ok(n))
+        });
+        assert!(run(err).is_err());
+        assert!(!called);
+    }
+
+    #[test]
+    fn or_else_recovers_and_map_err_converts() {
+        let recovered = parse("x").or_else(|_| ready(Ok::<i32, ()>(0)));
+        assert_eq!(run(recovered), Ok(0));
+
+        let converted = parse("x").map_err(|e| e.len());
+        assert!(matches!(run(converted), Err(len) if len > 0));
+    }
+
+    #[test]
+    fn err_into_unifies_error_types_for_try_join() {
+        #[derive(Debug, PartialEq)]
+        enum AppError {
+            Parse(String),
+            Io(std::io::ErrorKind),
+        }
+        impl From<String> for AppError {
+            fn from(e: String) -> Self {
+                AppError::Parse(e)
+            }
+        }
+        impl From<std::io::ErrorKind> for AppError {
+            fn from(e: std::io::ErrorKind) -> Self {
+                AppError::Io(e)
+            }
+        }
+
+        let io = ready(Err::<(), _>(std::io::ErrorKind::TimedOut));
+        let joined = crate::try_join(parse("1").err_into::<AppError>(), io.err_into());
+        assert_eq!(run(joined), Err(AppError::Io(std::io::ErrorKind::TimedOut)));
+
+        assert_eq!(run(parse("x").unwrap_or_else(|_| -1)), -1);
+    }
+
+    #[test]
+    fn then_and_map_chain_plain_outputs() {
+        let chained = ready(3).then(|n| ready(n * 10)).map(|n| n + 1);
+        assert_eq!(run(chained), 31);
+    }
+}
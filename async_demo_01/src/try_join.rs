@@ -0,0 +1,264 @@
+use std::task::Waker;
+
+use crate::simple_future::{Poll, SimpleFuture};
+use crate::try_future::TryFuture;
+
+/*
+    TryJoin 和 Join 一样并发地运行多个结果是 Result 的 Future，区别是：
+    1. 所有的都成功时返回 Ok((a 的结果, b 的结果))
+    2. 任何一个失败时立刻返回这个 Err，其他还没完成的 Future 直接 drop 掉，不会再被 poll
+
+    这就像同步代码中依次写 `a?; b?;`，只是 a、b 是同时运行的
+*/
+
+/// 和 MaybeDone 一样，只是子 Future 失败时把错误交给调用者
+pub(crate) enum TryMaybeDone<F: TryFuture> {
+    Future(F),
+    Done(F::Ok),
+    Gone,
+}
+
+impl<F: TryFuture> TryMaybeDone<F> {
+    // Ok(true) 表示已经成功完成了，Ok(false) 表示还没完成
+    pub(crate) fn poll(&mut self, waker: &Waker) -> Result<bool, F::Error> {
+        match self {
+            TryMaybeDone::Future(future) => match future.try_poll(waker) {
+                Poll::Ready(Ok(output)) => {
+                    *self = TryMaybeDone::Done(output);
+                    Ok(true)
+                }
+                Poll::Ready(Err(err)) => {
+                    *self = TryMaybeDone::Gone;
+                    Err(err)
+                }
+                Poll::Pending => Ok(false),
+            },
+            TryMaybeDone::Done(_) => Ok(true),
+            TryMaybeDone::Gone => panic!("TryMaybeDone polled after completion"),
+        }
+    }
+
+    pub(crate) fn take_output(&mut self) -> F::Ok {
+        match std::mem::replace(self, TryMaybeDone::Gone) {
+            TryMaybeDone::Done(output) => output,
+            _ => panic!("TryMaybeDone::take_output called before completion"),
+        }
+    }
+}
+
+pub struct TryJoin<FutureA: TryFuture, FutureB: TryFuture> {
+    a: TryMaybeDone<FutureA>,
+    b: TryMaybeDone<FutureB>,
+}
+
+/// 并发地完成两个结果是 Result 的 Future，遇到第一个 Err 就返回
+pub fn try_join<A, B>(a: A, b: B) -> TryJoin<A, B>
+where
+    A: TryFuture,
+    B: TryFuture<Error = A::Error>,
+{
+    TryJoin {
+        a: TryMaybeDone::Future(a),
+        b: TryMaybeDone::Future(b),
+    }
+}
+
+impl<FutureA, FutureB> SimpleFuture for TryJoin<FutureA, FutureB>
+where
+    FutureA: TryFuture,
+    FutureB: TryFuture<Error = FutureA::Error>,
+{
+    type Output = Result<(FutureA::Ok, FutureB::Ok), FutureA::Error>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let result = self
+            .a
+            .poll(waker)
+            .and_then(|a_done| Ok(self.b.poll(waker)? && a_done));
+        match result {
+            Ok(true) => Poll::Ready(Ok((self.a.take_output(), self.b.take_output()))),
+            Ok(false) => Poll::Pending,
+            Err(err) => {
+                // 另一个 Future 也不需要了，现在就 drop 掉
+                self.a = TryMaybeDone::Gone;
+                self.b = TryMaybeDone::Gone;
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+// TryJoin3、TryJoin4 和 TryJoin 的写法一样，和 Join3、Join4 一样用宏来生成
+macro_rules! generate_try_join {
+    ($(#[$doc:meta])* $name:ident, $fn_name:ident, $($F:ident $f:ident),+) => {
+        $(#[$doc])*
+        pub struct $name<$($F: TryFuture),+> {
+            $($f: TryMaybeDone<$F>,)+
+        }
+
+        impl<$($F: TryFuture),+> $name<$($F),+> {
+            // 失败时把其他的子 Future 都 drop 掉
+            fn cancel(&mut self) {
+                $(self.$f = TryMaybeDone::Gone;)+
+            }
+        }
+
+        impl<E, $($F),+> SimpleFuture for $name<$($F),+>
+        where
+            $($F: TryFuture<Error = E>,)+
+        {
+            type Output = Result<($($F::Ok,)+), E>;
+
+            fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+                let mut all_done = true;
+                $(
+                    match self.$f.poll(waker) {
+                        Ok(done) => all_done &= done,
+                        Err(err) => {
+                            self.cancel();
+                            return Poll::Ready(Err(err));
+                        }
+                    }
+                )+
+                if all_done {
+                    Poll::Ready(Ok(($(self.$f.take_output(),)+)))
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+
+        $(#[$doc])*
+        pub fn $fn_name<E, $($F),+>($($f: $F),+) -> $name<$($F),+>
+        where
+            $($F: TryFuture<Error = E>,)+
+        {
+            $name {
+                $($f: TryMaybeDone::Future($f),)+
+            }
+        }
+    };
+}
+
+generate_try_join!(
+    /// 并发地完成三个结果是 Result 的 Future，遇到第一个 Err 就返回
+    TryJoin3, try_join3, A a, B b, C c
+);
+generate_try_join!(
+    /// 并发地完成四个结果是 Result 的 Future，遇到第一个 Err 就返回
+    TryJoin4, try_join4, A a, B b, C c, D d
+);
+
+/*
+    TryJoinAll 是 JoinAll 的 Result 版本：
+    全部成功时按照传入的顺序返回结果，遇到第一个 Err 就返回它，并 drop 掉所有剩下的 Future
+*/
+pub struct TryJoinAll<F: TryFuture> {
+    /// 返回结果（不管成功还是失败）之后是 None
+    children: Option<Vec<TryMaybeDone<F>>>,
+}
+
+/// 并发地完成一组结果是 Result 的 Future，遇到第一个 Err 就返回
+pub fn try_join_all<I>(futures: I) -> TryJoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: TryFuture,
+{
+    TryJoinAll {
+        children: Some(futures.into_iter().map(TryMaybeDone::Future).collect()),
+    }
+}
+
+impl<F: TryFuture> SimpleFuture for TryJoinAll<F> {
+    type Output = Result<Vec<F::Ok>, F::Error>;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let children = self
+            .children
+            .as_mut()
+            .expect("TryJoinAll polled after completion");
+        let mut all_done = true;
+        for child in children.iter_mut() {
+            match child.poll(waker) {
+                Ok(done) => all_done &= done,
+                Err(err) => {
+                    self.children = None;
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+        if all_done {
+            let children = self.children.take().unwrap();
+            let outputs = children.into_iter().map(|mut child| child.take_output());
+            Poll::Ready(Ok(outputs.collect()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, ready};
+    use std::cell::Cell;
+
+    // 永远不会完成，drop 时记录下来
+    struct Forever<'a> {
+        dropped: &'a Cell<bool>,
+    }
+
+    impl SimpleFuture for Forever<'_> {
+        type Output = Result<(), &'static str>;
+
+        fn poll(&mut self, _waker: &Waker) -> Poll<Self::Output> {
+            Poll::Pending
+        }
+    }
+
+    impl Drop for Forever<'_> {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    #[test]
+    fn try_join_returns_both_or_first_error() {
+        let ok = try_join(ready(Ok::<_, &str>(1)), ready(Ok("two")));
+        assert_eq!(block_on(ok), Ok((1, "two")));
+
+        let dropped = Cell::new(false);
+        let mut err = try_join(Forever { dropped: &dropped }, ready(Err::<(), _>("boom")));
+        let waker = futures::task::noop_waker();
+        assert!(matches!(err.poll(&waker), Poll::Ready(Err("boom"))));
+        // 失败时另一个还在等待的 Future 立刻就被 drop 了
+        assert!(dropped.get());
+    }
+
+    #[test]
+    fn try_join_all_keeps_order_and_drops_the_rest_on_error() {
+        let all = try_join_all((1..=3).map(|n| ready(Ok::<_, String>(n))));
+        assert_eq!(block_on(all), Ok(vec![1, 2, 3]));
+
+        let dropped = Cell::new(false);
+        let forever =
+            || Box::new(Forever { dropped: &dropped }) as Box<dyn SimpleFuture<Output = _>>;
+        let failing = Box::new(ready(Err("boom")));
+        let mut all = try_join_all(vec![forever(), failing, forever()]);
+        let waker = futures::task::noop_waker();
+        assert!(matches!(all.poll(&waker), Poll::Ready(Err("boom"))));
+        assert!(dropped.get());
+
+        let three = try_join3(ready(Ok::<_, ()>(1)), ready(Ok('b')), ready(Ok("c")));
+        assert_eq!(block_on(three), Ok((1, 'b', "c")));
+    }
+
+    #[test]
+    #[should_panic(expected = "TryJoinAll polled after completion")]
+    fn try_join_all_panics_when_polled_after_an_error() {
+        let waker = futures::task::noop_waker();
+        let mut all = try_join_all(vec![ready(Err::<(), _>("boom"))]);
+        assert!(matches!(all.poll(&waker), Poll::Ready(Err("boom"))));
+        let _ = all.poll(&waker);
+    }
+}
@@ -0,0 +1,475 @@
+/*
+    有依赖关系的异步任务图（DAG），用于构建、数据处理这类流水线
+
+    let mut dag = Dag::new();
+    dag.add("fetch", &[], |_| async { Ok(download().await?) });
+    dag.add("parse", &["fetch"], |inputs| async move { parse(&inputs[0]) });
+    dag.add("index", &["fetch"], |inputs| async move { index(&inputs[0]) });
+    dag.add("report", &["parse", "index"], |inputs| async move { .. });
+    let outputs = dag.run().await?;
+
+    1. 开始运行之前先检查整个图：名字重复、依赖不存在、有环，都直接返回错误，一个任务都不会运行
+    2. 所有依赖都完成了的节点立刻开始运行，互不依赖的节点是并发的（parse 和 index 同时运行）
+    3. 节点的结果用 Arc 包起来，按照声明依赖的顺序传给每一个依赖它的节点
+    4. 某个节点失败时，所有直接、间接依赖它的节点都被取消，不会再运行；
+       和它无关的节点继续运行，最后把所有的失败和被取消的节点一起返回
+
+    每个节点的 Future 都是一个 BoxFuture，默认在调用 run 的任务中并发地 poll；
+    用 spawner 传入一个生成任务的函数（比如 timer_future_02 中 Spawner::spawn）后，
+    每个节点都作为一个单独的任务运行，可以利用多个线程。
+    run 返回的 Future 被 drop 时，还在运行的任务都会通过 CancellationToken 被取消
+*/
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    future::Future,
+    sync::Arc,
+};
+
+use futures::{
+    future::BoxFuture,
+    stream::{FuturesUnordered, StreamExt},
+    FutureExt,
+};
+use timer_future_02::{channel::oneshot, sync::CancellationToken};
+
+type Job<T, E> = Box<dyn FnOnce(Vec<Arc<T>>) -> BoxFuture<'static, Result<T, E>> + Send>;
+type Spawn = Box<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>;
+
+/// 任务图，所有节点的结果类型都是 T，错误类型都是 E
+pub struct Dag<T, E> {
+    nodes: Vec<Node<T, E>>,
+    spawn: Option<Spawn>,
+}
+
+struct Node<T, E> {
+    name: String,
+    deps: Vec<String>,
+    job: Job<T, E>,
+}
+
+impl<T, E> Default for Dag<T, E> {
+    fn default() -> Self {
+        Dag::new()
+    }
+}
+
+impl<T, E> Dag<T, E> {
+    pub fn new() -> Self {
+        Dag {
+            nodes: Vec::new(),
+            spawn: None,
+        }
+    }
+
+    /// 添加一个节点，`job` 拿到 `deps` 中每个节点的结果（顺序和 `deps` 一样）
+    pub fn add<F, Fut>(&mut self, name: &str, deps: &[&str], job: F) -> &mut Self
+    where
+        F: FnOnce(Vec<Arc<T>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+    {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            job: Box::new(move |inputs| job(inputs).boxed()),
+        });
+        self
+    }
+
+    /// 用 `spawn` 把每个节点作为单独的任务运行
+    pub fn spawner(
+        mut self,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> Self {
+        self.spawn = Some(Box::new(spawn));
+        self
+    }
+}
+
+impl<T, E> Dag<T, E>
+where
+    T: Send + Sync + 'static,
+    E: Send + 'static,
+{
+    /// 运行整个图，全部成功时返回每个节点的结果
+    pub async fn run(self) -> Result<DagOutputs<T>, DagError<E>> {
+        let graph = Graph::new(&self.nodes)?;
+        let Dag { nodes, spawn } = self;
+
+        let token = CancellationToken::new();
+        let _guard = token.clone().drop_guard();
+
+        let mut jobs: Vec<Option<Job<T, E>>> = Vec::with_capacity(nodes.len());
+        let mut names = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            jobs.push(Some(node.job));
+            names.push(node.name);
+        }
+        let mut waiting: Vec<usize> = graph.deps.iter().map(Vec::len).collect();
+        let mut outputs: Vec<Option<Arc<T>>> = names.iter().map(|_| None).collect();
+        let mut cancelled = vec![false; names.len()];
+        let mut failed = Vec::new();
+        let mut running = FuturesUnordered::new();
+
+        let start = |index: usize,
+                     jobs: &mut Vec<Option<Job<T, E>>>,
+                     outputs: &[Option<Arc<T>>]|
+         -> BoxFuture<'static, (usize, Option<Result<T, E>>)> {
+            let inputs = graph.deps[index]
+                .iter()
+                .map(|&dep| outputs[dep].clone().expect("dependency finished"))
+                .collect();
+            let future = (jobs[index].take().expect("node started twice"))(inputs);
+            match &spawn {
+                None => future.map(move |result| (index, Some(result))).boxed(),
+                Some(spawn) => {
+                    // 任务通过 oneshot 把结果送回来，任务 panic 或者被丢掉时 rx 得到 Err
+                    let (tx, rx) = oneshot::channel();
+                    let token = token.child_token();
+                    spawn(
+                        async move {
+                            if let Some(result) = token.run_until_cancelled(future).await {
+                                let _ = tx.send(result);
+                            }
+                        }
+                        .boxed(),
+                    );
+                    rx.map(move |result| (index, result.ok())).boxed()
+                }
+            }
+        };
+
+        for index in (0..names.len()).filter(|&index| waiting[index] == 0) {
+            running.push(start(index, &mut jobs, &outputs));
+        }
+
+        while let Some((index, result)) = running.next().await {
+            match result {
+                Some(Ok(output)) => {
+                    outputs[index] = Some(Arc::new(output));
+                    for &next in &graph.dependents[index] {
+                        waiting[next] -= 1;
+                        if waiting[next] == 0 && !cancelled[next] {
+                            running.push(start(next, &mut jobs, &outputs));
+                        }
+                    }
+                }
+                Some(Err(err)) => {
+                    failed.push((names[index].clone(), err));
+                    graph.cancel_dependents(index, &mut cancelled);
+                }
+                // 节点的任务没有完成就不见了，当作被取消
+                None => {
+                    cancelled[index] = true;
+                    graph.cancel_dependents(index, &mut cancelled);
+                }
+            }
+        }
+
+        let cancelled: Vec<String> = names
+            .iter()
+            .zip(&cancelled)
+            .filter(|(_, &cancelled)| cancelled)
+            .map(|(name, _)| name.clone())
+            .collect();
+        if failed.is_empty() && cancelled.is_empty() {
+            let outputs = names
+                .into_iter()
+                .zip(outputs)
+                .map(|(name, output)| (name, output.expect("all nodes finished")))
+                .collect();
+            Ok(DagOutputs { outputs })
+        } else {
+            Err(DagError::Failed { failed, cancelled })
+        }
+    }
+}
+
+// 用下标表示的依赖关系
+struct Graph {
+    /// 每个节点依赖的节点
+    deps: Vec<Vec<usize>>,
+    /// 每个节点被哪些节点依赖
+    dependents: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new<T, E>(nodes: &[Node<T, E>]) -> Result<Self, DagError<E>> {
+        let mut index = HashMap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            if index.insert(node.name.as_str(), i).is_some() {
+                return Err(DagError::DuplicateNode(node.name.clone()));
+            }
+        }
+
+        let mut deps = Vec::with_capacity(nodes.len());
+        let mut dependents = vec![Vec::new(); nodes.len()];
+        for (i, node) in nodes.iter().enumerate() {
+            let mut node_deps = Vec::with_capacity(node.deps.len());
+            for dep in &node.deps {
+                let &d = index
+                    .get(dep.as_str())
+                    .ok_or_else(|| DagError::UnknownDependency {
+                        node: node.name.clone(),
+                        dependency: dep.clone(),
+                    })?;
+                node_deps.push(d);
+                dependents[d].push(i);
+            }
+            deps.push(node_deps);
+        }
+
+        let graph = Graph { deps, dependents };
+        if let Some(cycle) = graph.find_cycle() {
+            let names = cycle.into_iter().map(|i| nodes[i].name.clone()).collect();
+            return Err(DagError::Cycle(names));
+        }
+        Ok(graph)
+    }
+
+    // 拓扑排序（Kahn 算法），排不完的节点都在环上或者依赖环上的节点
+    fn find_cycle(&self) -> Option<Vec<usize>> {
+        let mut waiting: Vec<usize> = self.deps.iter().map(Vec::len).collect();
+        let mut ready: Vec<usize> = (0..waiting.len()).filter(|&i| waiting[i] == 0).collect();
+        while let Some(i) = ready.pop() {
+            for &next in &self.dependents[i] {
+                waiting[next] -= 1;
+                if waiting[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+
+        // 剩下的节点至少有一个依赖也是剩下的，顺着这样的依赖一直走，一定会回到走过的节点
+        let start = waiting.iter().position(|&n| n > 0)?;
+        let mut path = vec![start];
+        let mut seen = HashSet::from([start]);
+        loop {
+            let current = *path.last().unwrap();
+            let next = self.deps[current]
+                .iter()
+                .copied()
+                .find(|&dep| waiting[dep] > 0)
+                .expect("remaining node has a remaining dependency");
+            if !seen.insert(next) {
+                let begin = path.iter().position(|&i| i == next).unwrap();
+                let mut cycle = path.split_off(begin);
+                // 按照执行的顺序排列：被依赖的在前面
+                cycle.reverse();
+                return Some(cycle);
+            }
+            path.push(next);
+        }
+    }
+
+    // 把所有直接、间接依赖 `index` 的节点标记为取消
+    fn cancel_dependents(&self, index: usize, cancelled: &mut [bool]) {
+        let mut stack = self.dependents[index].clone();
+        while let Some(i) = stack.pop() {
+            if !cancelled[i] {
+                cancelled[i] = true;
+                stack.extend_from_slice(&self.dependents[i]);
+            }
+        }
+    }
+}
+
+/// 所有节点的结果
+#[derive(Debug)]
+pub struct DagOutputs<T> {
+    outputs: HashMap<String, Arc<T>>,
+}
+
+impl<T> DagOutputs<T> {
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.outputs.get(name).map(|output| &**output)
+    }
+
+    /// 取出一个节点的结果，依赖它的节点可能还持有同一个 Arc
+    pub fn take(&mut self, name: &str) -> Option<Arc<T>> {
+        self.outputs.remove(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+/// 运行任务图的错误
+#[derive(Debug)]
+pub enum DagError<E> {
+    /// 有两个节点的名字相同
+    DuplicateNode(String),
+    /// 依赖了不存在的节点
+    UnknownDependency { node: String, dependency: String },
+    /// 环上的节点，按照依赖的顺序排列，最后一个依赖第一个
+    Cycle(Vec<String>),
+    /// 有节点失败了，`cancelled` 是因此没有运行的节点
+    Failed {
+        failed: Vec<(String, E)>,
+        cancelled: Vec<String>,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for DagError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagError::DuplicateNode(name) => write!(f, "duplicate node `{}`", name),
+            DagError::UnknownDependency { node, dependency } => {
+                write!(
+                    f,
+                    "node `{}` depends on unknown node `{}`",
+                    node, dependency
+                )
+            }
+            DagError::Cycle(names) => write!(f, "dependency cycle: {}", names.join(" -> ")),
+            DagError::Failed { failed, cancelled } => {
+                let failed: Vec<String> = failed
+                    .iter()
+                    .map(|(name, err)| format!("`{}`: {}", name, err))
+                    .collect();
+                write!(f, "nodes failed ({})", failed.join(", "))?;
+                if !cancelled.is_empty() {
+                    write!(f, ", cancelled: {}", cancelled.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for DagError<E> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Concurrency;
+    use futures::executor::block_on;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    // 在另一个线程中睡一会儿再返回 value
+    async fn slow<T: Send + 'static>(millis: u64, value: T) -> T {
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(millis));
+            let _ = tx.send(value);
+        });
+        rx.await.unwrap()
+    }
+
+    #[test]
+    fn diamond_runs_branches_in_parallel_and_passes_outputs() {
+        let concurrency = Concurrency::default();
+        let branch = |value: u32| {
+            let concurrency = concurrency.clone();
+            move |inputs: Vec<Arc<u32>>| async move {
+                let output = concurrency.track(slow(50, *inputs[0] * value)).await;
+                Ok::<_, String>(output)
+            }
+        };
+
+        let mut dag = Dag::new();
+        dag.add("sum", &["double", "triple"], |inputs| async move {
+            Ok(inputs.iter().map(|n| **n).sum())
+        })
+        .add("double", &["root"], branch(2))
+        .add("triple", &["root"], branch(3))
+        .add("root", &[], |_| async { Ok(10) });
+
+        let outputs = block_on(dag.run()).unwrap();
+        assert_eq!(outputs.get("sum"), Some(&50));
+        assert_eq!(outputs.len(), 4);
+        assert_eq!(concurrency.peak(), 2);
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected_before_running() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let job = || {
+            let started = started.clone();
+            move |_| async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                Ok::<u32, ()>(0)
+            }
+        };
+
+        let mut dag = Dag::new();
+        dag.add("free", &[], job())
+            .add("a", &["c"], job())
+            .add("b", &["a"], job())
+            .add("c", &["b", "free"], job());
+        match block_on(dag.run()) {
+            Err(DagError::Cycle(cycle)) => {
+                assert_eq!(cycle.len(), 3);
+                assert!(["a", "b", "c"]
+                    .iter()
+                    .all(|n| cycle.contains(&n.to_string())));
+            }
+            other => panic!("expected a cycle, got {:?}", other.map(|o| o.len())),
+        }
+
+        let mut dag = Dag::new();
+        dag.add("a", &["missing"], job());
+        assert!(matches!(
+            block_on(dag.run()),
+            Err(DagError::UnknownDependency { dependency, .. }) if dependency == "missing"
+        ));
+        assert_eq!(started.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn failure_cancels_only_downstream_nodes() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let job = |name: &'static str, fail: bool| {
+            let ran = ran.clone();
+            move |_| async move {
+                ran.lock().unwrap().push(name);
+                if fail {
+                    Err(format!("{} broke", name))
+                } else {
+                    slow(10, ()).await;
+                    Ok(())
+                }
+            }
+        };
+
+        // 用单独的线程运行每个节点，就像 Spawner::spawn 那样
+        let mut dag = Dag::new().spawner(|task| {
+            thread::spawn(move || block_on(task));
+        });
+        dag.add("fetch", &[], job("fetch", false))
+            .add("parse", &["fetch"], job("parse", true))
+            .add("index", &["parse"], job("index", false))
+            .add("report", &["index", "lint"], job("report", false))
+            .add("lint", &["fetch"], job("lint", false));
+
+        let err = block_on(dag.run()).unwrap_err();
+        let DagError::Failed {
+            failed,
+            mut cancelled,
+        } = err
+        else {
+            panic!("expected a failure");
+        };
+        assert_eq!(failed, [("parse".to_string(), "parse broke".to_string())]);
+        cancelled.sort();
+        assert_eq!(cancelled, ["index", "report"]);
+
+        let mut ran = ran.lock().unwrap().clone();
+        ran.sort();
+        assert_eq!(ran, ["fetch", "lint", "parse"]);
+    }
+}
@@ -0,0 +1,26 @@
+/*
+    第 7 章的例子：main.rs 中是 async 的临时解决方案、Send 近似和递归，
+    这里把 7.3 中 BoxFuture 递归的写法用到实际的场景中：
+
+    - walk_dir：用递归的 BoxFuture 并发地遍历目录树，结果是一个 Stream
+    - recursion：用蹦床运行递归，复用每一层的内存，超过深度限制时返回错误
+    - dag：有依赖关系的任务图，每个节点是一个 BoxFuture，尽可能并发地运行
+    - service：call 返回 BoxFuture 的 Service trait，可以做成 trait 对象，用 Layer 一层层地包装
+*/
+
+pub mod dag;
+pub mod recursion;
+pub mod service;
+pub mod walk_dir;
+
+#[cfg(test)]
+mod test_util;
+
+pub use dag::{Dag, DagError, DagOutputs};
+pub use recursion::{Call, DepthExceeded, PoolStats, Recurse, Recursion, Trampoline};
+pub use service::{
+    service_fn, BoxService, Breaker, CircuitBreakerLayer, ConcurrencyLimit, ConcurrencyLimitLayer,
+    Layer, RateLimit, RateLimitLayer, Retry, RetryLayer, Service, ServiceExt, ServiceFn, Timeout,
+    TimeoutLayer,
+};
+pub use walk_dir::{walk_dir, DirEntry, WalkDir, WalkDirStream, WalkError};
@@ -83,7 +83,10 @@
 
 
 use futures::future::{BoxFuture, FutureExt};
+use futures::StreamExt;
 
+// 只是演示写法，真的调用会无限递归下去
+#[allow(dead_code)]
 fn recursive() -> BoxFuture<'static, ()> {
     async move {
         recursive().await;
@@ -91,8 +94,21 @@ fn recursive() -> BoxFuture<'static, ()> {
     }.boxed()
 }
 
+// 7.3 递归的实际应用：并发地遍历目录树，cargo run -- <目录> [最大深度]
 fn main() {
-    
+    let mut args = std::env::args().skip(1);
+    let root = args.next().unwrap_or_else(|| ".".to_string());
+    let mut walk = async_q_07::WalkDir::new(root).max_open(4);
+    if let Some(depth) = args.next().and_then(|depth| depth.parse().ok()) {
+        walk = walk.max_depth(depth);
+    }
+
+    futures::executor::block_on(walk.into_stream().for_each(|entry| async move {
+        match entry {
+            Ok(entry) => println!("{}{}", "  ".repeat(entry.depth() - 1), entry.path().display()),
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }));
 }
 
 
@@ -0,0 +1,515 @@
+/*
+    不会栈溢出、可以复用内存的异步递归
+
+    7.3 中的 recursive() 每递归一层都要 Box 一次，并且没有深度限制：
+    1. 每一层都是一次新的堆分配，递归结束后又全部释放掉
+    2. poll 最外层的 Future 时会一层一层地 poll 到最里层，递归很深时 poll 本身就会把栈用完
+    3. 无限递归时只能等着内存或者栈被耗尽
+
+    这里用蹦床（trampoline）的方式来运行递归：
+    - 每一层递归都是一个 Frame，放在 Trampoline 自己维护的栈（Vec）中，而不是嵌套在上一层的 Future 里面
+    - 上一层调用 `rec.call(下一层)` 时，下一层的 Future 被放进池子分配的内存中，
+      上一层返回 Pending，Trampoline 看到有新的调用，就把它压到栈上接着 poll
+    - 最上面的 Frame 完成后出栈，结果交给下一个 Frame 中等待的 Call，再 poll 那个 Frame
+    这样不管递归多深，每次 poll 都只有 Trampoline -> 最上面的 Frame 这两层，栈不会溢出
+
+    Frame 出栈后它的内存不会释放，而是按照 Layout 放回池子，下一次同样大小的调用直接复用，
+    递归函数每一层的 Future 类型都是一样的，所以像 fib 这样的树形递归只需要 “最大深度” 次分配。
+    递归超过 max_depth 时 `rec.call` 直接返回 DepthExceeded，不会再分配内存
+
+    限制：同一个 Frame 一次只能等待一个递归调用（就像同步的递归一样），
+    不能用 join 同时等待多个 `rec.call`，需要并发时用 walk_dir 中 join_all 的写法
+*/
+
+use std::{
+    alloc::{self, Layout},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    ptr::{self, NonNull},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// 递归的配置
+#[derive(Debug, Clone)]
+pub struct Recursion {
+    max_depth: usize,
+    pool_size: usize,
+}
+
+impl Default for Recursion {
+    fn default() -> Self {
+        Recursion::new()
+    }
+}
+
+impl Recursion {
+    pub fn new() -> Self {
+        Recursion {
+            max_depth: 10_000,
+            pool_size: 64,
+        }
+    }
+
+    /// 最多同时存在几层递归（包括最外层），默认是 10000
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        assert!(depth > 0, "max_depth must be at least one");
+        self.max_depth = depth;
+        self
+    }
+
+    /// 每种大小的内存最多在池子中留几块，默认是 64，超过的直接释放
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool_size = size;
+        self
+    }
+
+    /// 运行一个递归：`f` 拿到 Recurse 后返回最外层的 Future
+    pub fn run<'a, T, F, Fut>(self, f: F) -> Trampoline<'a, T>
+    where
+        F: FnOnce(Recurse<'a, T>) -> Fut,
+        Fut: Future<Output = T> + Send + 'a,
+    {
+        let state = Arc::new(Mutex::new(State {
+            pool: Pool {
+                free: HashMap::new(),
+                capacity: self.pool_size,
+                stats: PoolStats::default(),
+            },
+            call: None,
+            output: None,
+            depth: 1,
+            max_depth: self.max_depth,
+        }));
+        let root = f(Recurse {
+            state: state.clone(),
+        });
+        let root = Frame::new(&mut state.lock().unwrap().pool, root);
+        Trampoline {
+            state,
+            stack: vec![root],
+        }
+    }
+}
+
+/// 递归深度超过了 max_depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthExceeded {
+    limit: usize,
+}
+
+impl DepthExceeded {
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for DepthExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "async recursion exceeded the depth limit of {}",
+            self.limit
+        )
+    }
+}
+
+impl Error for DepthExceeded {}
+
+/// 内存池的统计，用来观察复用的效果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// 真正向分配器申请的次数
+    pub allocated: usize,
+    /// 从池子中复用的次数
+    pub reused: usize,
+}
+
+/// 传给每一层递归的句柄，用来发起下一层递归
+pub struct Recurse<'a, T> {
+    state: Arc<Mutex<State<'a, T>>>,
+}
+
+impl<T> Clone for Recurse<'_, T> {
+    fn clone(&self) -> Self {
+        Recurse {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<'a, T> Recurse<'a, T> {
+    /// 把 `future` 作为下一层递归运行，超过深度限制时返回 DepthExceeded
+    pub fn call<F>(&self, future: F) -> Call<'a, T>
+    where
+        F: Future<Output = T> + Send + 'a,
+    {
+        let mut state = self.state.lock().unwrap();
+        let call = if state.depth >= state.max_depth {
+            CallState::Exceeded(state.max_depth)
+        } else {
+            CallState::Start(Frame::new(&mut state.pool, future))
+        };
+        Call {
+            state: self.state.clone(),
+            call,
+        }
+    }
+
+    /// 当前的递归深度，最外层是 1
+    pub fn depth(&self) -> usize {
+        self.state.lock().unwrap().depth
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.state.lock().unwrap().pool.stats
+    }
+}
+
+/// `Recurse::call` 返回的 Future，结果是下一层递归的结果
+pub struct Call<'a, T> {
+    state: Arc<Mutex<State<'a, T>>>,
+    call: CallState<'a, T>,
+}
+
+enum CallState<'a, T> {
+    Exceeded(usize),
+    Start(Frame<'a, T>),
+    Waiting,
+    Done,
+}
+
+impl<T> Future for Call<'_, T> {
+    type Output = Result<T, DepthExceeded>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match std::mem::replace(&mut this.call, CallState::Done) {
+            CallState::Exceeded(limit) => Poll::Ready(Err(DepthExceeded { limit })),
+            CallState::Start(frame) => {
+                // 交给 Trampoline 压到栈上，它在同一次 poll 中就会接着运行下一层，所以不需要唤醒
+                let mut state = this.state.lock().unwrap();
+                assert!(
+                    state.call.is_none(),
+                    "only one recursive call can be awaited at a time"
+                );
+                state.call = Some(frame);
+                state.output = None;
+                this.call = CallState::Waiting;
+                Poll::Pending
+            }
+            // 下一层完成之前 Trampoline 不会 poll 这一层
+            CallState::Waiting => match this.state.lock().unwrap().output.take() {
+                Some(output) => Poll::Ready(Ok(output)),
+                None => {
+                    this.call = CallState::Waiting;
+                    Poll::Pending
+                }
+            },
+            CallState::Done => panic!("Call polled after completion"),
+        }
+    }
+}
+
+/// 运行递归的 Future，结果是最外层的结果
+pub struct Trampoline<'a, T> {
+    state: Arc<Mutex<State<'a, T>>>,
+    stack: Vec<Frame<'a, T>>,
+}
+
+impl<T> Future for Trampoline<'_, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = self.get_mut();
+        loop {
+            let top = this
+                .stack
+                .last_mut()
+                .expect("Trampoline polled after completion");
+            match top.poll(cx) {
+                Poll::Ready(output) => {
+                    // 先在锁外面 drop 掉这一层的 Future，再把内存放回池子
+                    let block = this.stack.pop().unwrap().into_block();
+                    let mut state = this.state.lock().unwrap();
+                    state.pool.release(block);
+                    // 这一层发起了调用却没有等它就完成了，那个调用也不会再有人等了
+                    if let Some(orphan) = state.call.take() {
+                        drop(state);
+                        let block = orphan.into_block();
+                        state = this.state.lock().unwrap();
+                        state.pool.release(block);
+                    }
+                    state.depth = this.stack.len();
+                    if this.stack.is_empty() {
+                        return Poll::Ready(output);
+                    }
+                    state.output = Some(output);
+                }
+                Poll::Pending => {
+                    let mut state = this.state.lock().unwrap();
+                    match state.call.take() {
+                        Some(frame) => {
+                            this.stack.push(frame);
+                            state.depth = this.stack.len();
+                        }
+                        // 在等待其他的东西（IO、定时器），它们会唤醒我们
+                        None => return Poll::Pending,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for Trampoline<'_, T> {
+    fn drop(&mut self) {
+        // 从最里层开始 drop
+        while let Some(frame) = self.stack.pop() {
+            drop(frame);
+        }
+    }
+}
+
+// 所有的 Frame 和 Call 共享的状态
+struct State<'a, T> {
+    pool: Pool,
+
+    /// 刚刚发起、还没有压到栈上的调用
+    call: Option<Frame<'a, T>>,
+
+    /// 刚刚完成的那一层的结果，等着交给下一层的 Call
+    output: Option<T>,
+    depth: usize,
+    max_depth: usize,
+}
+
+// 一块还没有放东西的内存
+struct Block {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// Block 只是一块内存，谁拥有它谁就可以使用
+unsafe impl Send for Block {}
+
+impl Block {
+    fn dealloc(self) {
+        if self.layout.size() != 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+        }
+    }
+}
+
+// 按照 Layout 分类保存空闲的内存
+struct Pool {
+    free: HashMap<Layout, Vec<Block>>,
+    capacity: usize,
+    stats: PoolStats,
+}
+
+impl Pool {
+    fn alloc<F>(&mut self) -> Block {
+        let layout = Layout::new::<F>();
+        if layout.size() == 0 {
+            return Block {
+                ptr: NonNull::<F>::dangling().cast(),
+                layout,
+            };
+        }
+        if let Some(block) = self.free.get_mut(&layout).and_then(Vec::pop) {
+            self.stats.reused += 1;
+            return block;
+        }
+        self.stats.allocated += 1;
+        let ptr = unsafe { alloc::alloc(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Block { ptr, layout }
+    }
+
+    fn release(&mut self, block: Block) {
+        if block.layout.size() == 0 {
+            return;
+        }
+        let free = self.free.entry(block.layout).or_default();
+        if free.len() < self.capacity {
+            free.push(block);
+        } else {
+            block.dealloc();
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for block in self.free.drain().flat_map(|(_, blocks)| blocks) {
+            block.dealloc();
+        }
+    }
+}
+
+// 放在池子分配的内存中的一层递归，相当于一个 Pin<Box<dyn Future>>，只是内存来自池子
+struct Frame<'a, T> {
+    future: NonNull<dyn Future<Output = T> + Send + 'a>,
+    layout: Layout,
+}
+
+// 里面的 Future 是 Send 的
+unsafe impl<T> Send for Frame<'_, T> {}
+
+impl<'a, T> Frame<'a, T> {
+    fn new<F>(pool: &mut Pool, future: F) -> Self
+    where
+        F: Future<Output = T> + Send + 'a,
+    {
+        let block = pool.alloc::<F>();
+        let ptr = block.ptr.cast::<F>();
+        unsafe { ptr.as_ptr().write(future) };
+        Frame {
+            future: ptr,
+            layout: block.layout,
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        // Future 在 drop 之前一直留在这块内存中，不会被移动
+        unsafe { Pin::new_unchecked(self.future.as_mut()) }.poll(cx)
+    }
+
+    // drop 掉里面的 Future，把内存还回来
+    fn into_block(self) -> Block {
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::drop_in_place(this.future.as_ptr()) };
+        Block {
+            ptr: this.future.cast(),
+            layout: this.layout,
+        }
+    }
+}
+
+impl<T> Drop for Frame<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.future.as_ptr()) };
+        Block {
+            ptr: self.future.cast(),
+            layout: self.layout,
+        }
+        .dealloc();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::manual_async_fn)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    type Rec = Recurse<'static, Result<u64, DepthExceeded>>;
+
+    // 和 7.3 一样，递归的函数要写出返回类型是 Send 的，编译器才不用去推断它自己
+    fn sum(rec: Rec, n: u64) -> impl Future<Output = Result<u64, DepthExceeded>> + Send {
+        async move {
+            if n == 0 {
+                return Ok(0);
+            }
+            let rest = rec.call(sum(rec.clone(), n - 1)).await??;
+            Ok(n + rest)
+        }
+    }
+
+    fn fib(rec: Rec, n: u64) -> impl Future<Output = Result<u64, DepthExceeded>> + Send {
+        async move {
+            if n < 2 {
+                return Ok(n);
+            }
+            let a = rec.call(fib(rec.clone(), n - 1)).await??;
+            let b = rec.call(fib(rec.clone(), n - 2)).await??;
+            Ok(a + b)
+        }
+    }
+
+    #[test]
+    fn deep_recursion_does_not_overflow_the_stack() {
+        // 直接嵌套 BoxFuture 的话，poll 十万层早就把测试线程的栈用完了
+        let result = block_on(
+            Recursion::new()
+                .max_depth(200_000)
+                .run(|rec| sum(rec, 100_000)),
+        );
+        assert_eq!(result, Ok(5_000_050_000));
+    }
+
+    #[test]
+    fn tree_recursion_reuses_frames() {
+        let mut stats = None;
+        let result = block_on(Recursion::new().run(|rec| {
+            stats = Some(rec.clone());
+            fib(rec, 20)
+        }));
+        assert_eq!(result, Ok(6765));
+
+        // 两万多次调用，只在第一次到达每个深度时分配
+        let stats = stats.unwrap().stats();
+        assert!(stats.allocated <= 20, "{stats:?}");
+        assert!(stats.reused > 20_000, "{stats:?}");
+    }
+
+    #[test]
+    fn depth_limit_returns_an_error() {
+        let result = block_on(Recursion::new().max_depth(50).run(|rec| sum(rec, 1_000)));
+        assert_eq!(result, Err(DepthExceeded { limit: 50 }));
+
+        let result = block_on(Recursion::new().max_depth(50).run(|rec| sum(rec, 49)));
+        assert_eq!(result, Ok(49 * 50 / 2));
+    }
+
+    #[test]
+    fn call_started_but_never_awaited_is_dropped() {
+        fn abandon(rec: Rec) -> impl Future<Output = Result<u64, DepthExceeded>> + Send {
+            async move {
+                // 只 poll 一次，调用已经交给了 Trampoline，然后不等它就返回
+                let mut call = rec.call(sum(rec.clone(), 3));
+                assert!(futures::poll!(&mut call).is_pending());
+                Ok(7)
+            }
+        }
+        fn root(rec: Rec) -> impl Future<Output = Result<u64, DepthExceeded>> + Send {
+            async move {
+                let a = rec.call(abandon(rec.clone())).await??;
+                // 留下的调用如果还在，这里会被当成 “同时等待两个调用”
+                let b = rec.call(sum(rec.clone(), 3)).await??;
+                Ok(a + b)
+            }
+        }
+        let mut stats = None;
+        let result = block_on(Recursion::new().run(|rec| {
+            stats = Some(rec.clone());
+            root(rec)
+        }));
+        assert_eq!(result, Ok(13));
+        // 被丢下的那一层的内存也回到了池子里，被后面的 sum 复用
+        assert!(stats.unwrap().stats().reused > 0);
+    }
+
+    #[test]
+    fn frames_can_await_other_futures() {
+        fn countdown(rec: Rec, n: u64) -> impl Future<Output = Result<u64, DepthExceeded>> + Send {
+            async move {
+                if n == 0 {
+                    return Ok(0);
+                }
+                // 每一层都等待另一个线程的结果
+                let (tx, rx) = futures::channel::oneshot::channel();
+                std::thread::spawn(move || tx.send(n).unwrap());
+                let value = rx.await.unwrap();
+                Ok(value + rec.call(countdown(rec.clone(), n - 1)).await??)
+            }
+        }
+        let result = block_on(Recursion::new().run(|rec| countdown(rec, 10)));
+        assert_eq!(result, Ok(55));
+    }
+}
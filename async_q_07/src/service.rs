@@ -0,0 +1,499 @@
+/*
+    用 BoxFuture 实现的、可以做成 trait 对象的异步 Service
+
+    trait 中的 async fn 返回的 Future 类型各不相同，所以不能放进 `dyn Trait`；
+    call 返回 BoxFuture，和 7.3 中递归的写法一样，用一次堆分配换来统一的类型：
+
+    let client = Client
+        .layer(TimeoutLayer::new(Duration::from_secs(10)))
+        .layer(RetryLayer::new(RetryPolicy::new(), is_transient));
+    client.ready().await?;
+    let response = client.call(request).await?;
+
+    - poll_ready：服务现在能不能接受新的请求，Pending 时调用方应该等它变成 Ready 再 call
+    - call：处理一个请求，只需要 &self，同一个服务可以被很多任务同时调用
+    - Layer：把一个服务包装成另一个服务的中间件，这里有超时、重试、限速、并发数限制和熔断
+    - boxed：把层层包装之后很长的类型擦除成 BoxService
+
+    限速和并发数限制都是在 call 返回的 Future 里排队，而不是在 poll_ready 里：
+    poll_ready 只有 &self，在那里拿到的许可没有地方放，多个调用方之间也分不清是谁的
+*/
+
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use timer_future_02::{
+    circuit_breaker::{CircuitBreaker, CircuitOpen},
+    rate_limit::{RateLimiter, SlidingWindow},
+    retry::{retry_if, RetryPolicy},
+    sync::Semaphore,
+    time::{timeout, Elapsed},
+};
+
+/// 接受 `Request`，异步地返回 `Response` 的服务
+pub trait Service<Request> {
+    type Response;
+    type Error;
+
+    /// 默认总是可以接受请求
+    fn poll_ready(&self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request) -> BoxFuture<'_, Result<Self::Response, Self::Error>>;
+}
+
+/// 擦除了具体类型的服务
+pub type BoxService<Request, Response, Error> =
+    Box<dyn Service<Request, Response = Response, Error = Error> + Send + Sync>;
+
+impl<S: Service<R> + ?Sized, R> Service<R> for Box<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        (**self).poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        (**self).call(req)
+    }
+}
+
+impl<S: Service<R> + ?Sized, R> Service<R> for Arc<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        (**self).poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        (**self).call(req)
+    }
+}
+
+/// 把一个服务包装成另一个服务
+pub trait Layer<S> {
+    type Service;
+
+    fn layer(&self, inner: S) -> Self::Service;
+}
+
+pub trait ServiceExt<R>: Service<R> {
+    /// 等到服务可以接受请求
+    fn ready(&self) -> Ready<'_, Self, R> {
+        Ready {
+            service: self,
+            _request: PhantomData,
+        }
+    }
+
+    /// 用 `layer` 包装自己，可以一层一层地链下去，最后加上的在最外层
+    fn layer<L: Layer<Self>>(self, layer: L) -> L::Service
+    where
+        Self: Sized,
+    {
+        layer.layer(self)
+    }
+
+    fn boxed(self) -> BoxService<R, Self::Response, Self::Error>
+    where
+        Self: Sized + Send + Sync + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+impl<S: Service<R> + ?Sized, R> ServiceExt<R> for S {}
+
+/// `ServiceExt::ready` 返回的 Future
+pub struct Ready<'a, S: ?Sized, R> {
+    service: &'a S,
+    _request: PhantomData<fn(R)>,
+}
+
+impl<S: Service<R> + ?Sized, R> Future for Ready<'_, S, R> {
+    type Output = Result<(), S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.service.poll_ready(cx)
+    }
+}
+
+/// 用闭包实现的服务，闭包返回的 Future 不能借用任何东西
+pub fn service_fn<F>(f: F) -> ServiceFn<F> {
+    ServiceFn { f }
+}
+
+pub struct ServiceFn<F> {
+    f: F,
+}
+
+impl<F, Fut, R, T, E> Service<R> for ServiceFn<F>
+where
+    F: Fn(R) -> Fut,
+    Fut: Future<Output = Result<T, E>> + Send + 'static,
+{
+    type Response = T;
+    type Error = E;
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<T, E>> {
+        Box::pin((self.f)(req))
+    }
+}
+
+/// 超时的请求返回 `Elapsed` 转换成的错误，比如 `io::ErrorKind::TimedOut`
+pub struct TimeoutLayer {
+    duration: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(duration: Duration) -> Self {
+        TimeoutLayer { duration }
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Timeout<S> {
+        Timeout {
+            inner,
+            duration: self.duration,
+        }
+    }
+}
+
+pub struct Timeout<S> {
+    inner: S,
+    duration: Duration,
+}
+
+impl<S, R> Service<R> for Timeout<S>
+where
+    S: Service<R> + Sync,
+    S::Response: Send + 'static,
+    S::Error: From<Elapsed> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        let response = self.inner.call(req);
+        Box::pin(async move { timeout(self.duration, response).await? })
+    }
+}
+
+/// 按照 RetryPolicy 重试 `is_retryable` 返回 true 的错误，每次重试都把请求 clone 一份
+pub struct RetryLayer<P> {
+    policy: RetryPolicy,
+    is_retryable: P,
+}
+
+impl<P> RetryLayer<P> {
+    pub fn new(policy: RetryPolicy, is_retryable: P) -> Self {
+        RetryLayer {
+            policy,
+            is_retryable,
+        }
+    }
+}
+
+impl<S, P: Clone> Layer<S> for RetryLayer<P> {
+    type Service = Retry<S, P>;
+
+    fn layer(&self, inner: S) -> Retry<S, P> {
+        Retry {
+            inner,
+            policy: self.policy.clone(),
+            is_retryable: self.is_retryable.clone(),
+        }
+    }
+}
+
+pub struct Retry<S, P> {
+    inner: S,
+    policy: RetryPolicy,
+    is_retryable: P,
+}
+
+impl<S, P, R> Service<R> for Retry<S, P>
+where
+    S: Service<R> + Sync,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+    P: Fn(&S::Error) -> bool + Sync,
+    R: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        Box::pin(async move {
+            let req = &req;
+            retry_if(
+                &self.policy,
+                || self.inner.call(req.clone()),
+                |err| (self.is_retryable)(err),
+            )
+            .await
+        })
+    }
+}
+
+/// 任意 `per` 这么长的时间里最多开始 `num` 个请求，多出来的请求排队等待
+pub struct RateLimitLayer {
+    num: u32,
+    per: Duration,
+}
+
+impl RateLimitLayer {
+    pub fn new(num: u32, per: Duration) -> Self {
+        assert!(num > 0, "rate limit must allow at least one request");
+        RateLimitLayer { num, per }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> RateLimit<S> {
+        RateLimit {
+            inner,
+            limiter: SlidingWindow::new(self.num, self.per),
+        }
+    }
+}
+
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: SlidingWindow,
+}
+
+impl<S, R> Service<R> for RateLimit<S>
+where
+    S: Service<R> + Sync,
+    R: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        Box::pin(async move {
+            self.limiter.acquire().await;
+            self.inner.call(req).await
+        })
+    }
+}
+
+/// 同时最多有 `max` 个请求在 inner 中处理，其他的按先来后到排队
+pub struct ConcurrencyLimitLayer {
+    max: usize,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "concurrency limit must be at least one");
+        ConcurrencyLimitLayer { max }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> ConcurrencyLimit<S> {
+        ConcurrencyLimit {
+            inner,
+            semaphore: Semaphore::new(self.max),
+        }
+    }
+}
+
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    semaphore: Semaphore,
+}
+
+impl<S, R> Service<R> for ConcurrencyLimit<S>
+where
+    S: Service<R> + Sync,
+    R: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        Box::pin(async move {
+            // 信号量从来不会被关闭；请求完成或者被取消时许可被还回去
+            let _permit = self.semaphore.acquire().await.unwrap();
+            self.inner.call(req).await
+        })
+    }
+}
+
+/// 用熔断器保护 inner，熔断时直接返回 CircuitOpen 转换成的错误；
+/// 同一个上游的所有服务应该共用一个熔断器，所以传入的是 Arc
+pub struct CircuitBreakerLayer {
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CircuitBreakerLayer {
+    pub fn new(breaker: Arc<CircuitBreaker>) -> Self {
+        CircuitBreakerLayer { breaker }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = Breaker<S>;
+
+    fn layer(&self, inner: S) -> Breaker<S> {
+        Breaker {
+            inner,
+            breaker: self.breaker.clone(),
+        }
+    }
+}
+
+pub struct Breaker<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<S, R> Service<R> for Breaker<S>
+where
+    S: Service<R> + Sync,
+    S::Response: Send + 'static,
+    S::Error: From<CircuitOpen> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: R) -> BoxFuture<'_, Result<S::Response, S::Error>> {
+        Box::pin(self.breaker.call(self.inner.call(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Concurrency;
+    use futures::{executor::block_on, future::join_all};
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    };
+    use timer_future_02::{retry::Jitter, time::sleep};
+
+    #[test]
+    fn layered_services_are_object_safe() {
+        let echo = service_fn(|millis: u64| async move {
+            sleep(Duration::from_millis(millis)).await;
+            Ok::<_, io::Error>(millis * 2)
+        });
+        let services: Vec<BoxService<u64, u64, io::Error>> = vec![
+            echo.layer(TimeoutLayer::new(Duration::from_millis(50)))
+                .boxed(),
+            service_fn(|_: u64| async { Err(io::Error::other("down")) }).boxed(),
+        ];
+
+        block_on(async {
+            services[0].ready().await.unwrap();
+            assert_eq!(services[0].call(5).await.unwrap(), 10);
+            let err = services[0].call(500).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert_eq!(services[1].call(5).await.unwrap_err().to_string(), "down");
+        });
+    }
+
+    #[test]
+    fn retry_wraps_timeout_and_skips_permanent_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        // 第一次调用很慢，会超时；之后的调用很快
+        let flaky = service_fn({
+            let calls = calls.clone();
+            move |path: &'static str| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if path == "/missing" {
+                        return Err(io::Error::from(io::ErrorKind::NotFound));
+                    }
+                    if call == 0 {
+                        sleep(Duration::from_millis(200)).await;
+                    }
+                    Ok(format!("{path} after {} calls", call + 1))
+                }
+            }
+        });
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(1))
+            .jitter(Jitter::None);
+        let client = flaky
+            .layer(TimeoutLayer::new(Duration::from_millis(20)))
+            .layer(RetryLayer::new(policy, |err: &io::Error| {
+                err.kind() == io::ErrorKind::TimedOut
+            }));
+
+        assert_eq!(block_on(client.call("/")).unwrap(), "/ after 2 calls");
+        let err = block_on(client.call("/missing")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn rate_and_concurrency_limits_delay_extra_requests() {
+        let concurrency = Concurrency::default();
+        let work = service_fn({
+            let concurrency = concurrency.clone();
+            move |()| {
+                let concurrency = concurrency.clone();
+                async move {
+                    concurrency.track(sleep(Duration::from_millis(10))).await;
+                    Ok::<_, ()>(())
+                }
+            }
+        });
+        let limited = work.layer(ConcurrencyLimitLayer::new(2));
+        block_on(join_all((0..6).map(|_| limited.call(()))));
+        assert_eq!(concurrency.peak(), 2);
+
+        // 每 40ms 最多 2 个：5 个请求要用 3 个窗口
+        let throttled = service_fn(|()| async { Ok::<_, ()>(Instant::now()) })
+            .layer(RateLimitLayer::new(2, Duration::from_millis(40)));
+        let start = Instant::now();
+        let times: Vec<_> = block_on(join_all((0..5).map(|_| throttled.call(()))))
+            .into_iter()
+            .map(|time| time.unwrap() - start)
+            .collect();
+        assert!(times[..2].iter().all(|t| *t < Duration::from_millis(40)));
+        assert!(times[4] >= Duration::from_millis(80));
+    }
+}
@@ -0,0 +1,31 @@
+// 测试中共用的小工具
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// 记录同时在运行的 Future 最多有几个，clone 出来的共享同一份计数
+#[derive(Clone, Default)]
+pub(crate) struct Concurrency {
+    active: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl Concurrency {
+    /// 运行 `future`，运行期间算作一个同时在运行的 Future
+    pub(crate) async fn track<F: Future>(&self, future: F) -> F::Output {
+        let now = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        let output = future.await;
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        output
+    }
+
+    pub(crate) fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,483 @@
+/*
+    异步地遍历目录树
+
+    7.3 中的 recursive() 说明了递归的 async fn 必须返回 BoxFuture，这里就用同样的写法：
+    visit 读取一个目录后，为每个子目录再调用 visit，得到的 BoxFuture 用 join_all 一起等待，
+    这样同一层的子目录都是并发地遍历的。
+
+    1. 读目录是阻塞的系统调用，所以放到单独的线程中去做（unblock），不会阻塞执行者
+    2. 同时读取的目录个数用 Semaphore 限制（max_open），目录再多也不会一下子开出成百上千个线程
+    3. 找到的条目通过有界的 channel 交给 WalkDirStream，调用者不去取的话遍历就停下来等待（背压）
+    4. 读取失败（比如没有权限）时产生一个 Err，然后继续遍历其他的目录
+    5. 跟随符号链接时，用规范化之后的路径记录从根目录到当前目录的所有祖先，
+       子目录是它的某个祖先时就产生一个 Loop 错误，不再进入，避免无限循环
+*/
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    thread,
+};
+
+use futures::{
+    future::{join_all, BoxFuture},
+    FutureExt, Stream, StreamExt,
+};
+use timer_future_02::{
+    channel::{mpsc, oneshot},
+    sync::Semaphore,
+};
+
+/// 遍历的配置
+#[derive(Debug, Clone)]
+pub struct WalkDir {
+    root: PathBuf,
+    max_depth: usize,
+    follow_links: bool,
+    max_open: usize,
+}
+
+impl WalkDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        WalkDir {
+            root: root.into(),
+            max_depth: usize::MAX,
+            follow_links: false,
+            max_open: 8,
+        }
+    }
+
+    /// 最多深入几层，1 表示只列出根目录下的条目，0 表示什么都不列出
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// 是否进入指向目录的符号链接，默认不进入
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// 同时读取的目录个数上限，默认是 8
+    pub fn max_open(mut self, n: usize) -> Self {
+        assert!(n > 0, "max_open must be at least one");
+        self.max_open = n;
+        self
+    }
+
+    pub fn into_stream(self) -> WalkDirStream {
+        let (tx, rx) = mpsc::channel(self.max_open * 16);
+        let depth_limit = self.max_depth;
+        let root = self.root.clone();
+        let walker = Arc::new(Walker {
+            follow_links: self.follow_links,
+            max_depth: self.max_depth,
+            open: Semaphore::new(self.max_open),
+            tx,
+        });
+        let driver = if depth_limit == 0 {
+            None
+        } else {
+            Some(visit(walker, root, 0, Arc::new(Vec::new())))
+        };
+        WalkDirStream { driver, rx }
+    }
+}
+
+/// 用默认的配置遍历 `root` 下面的所有条目（不包括 root 本身）
+pub fn walk_dir(root: impl Into<PathBuf>) -> WalkDirStream {
+    WalkDir::new(root).into_stream()
+}
+
+/// 遍历到的一个条目
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    depth: usize,
+    file_type: fs::FileType,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// 根目录下的条目是 1，再下一层是 2，以此类推
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// 条目本身的类型，符号链接不会被跟随
+    pub fn file_type(&self) -> fs::FileType {
+        self.file_type
+    }
+}
+
+/// 遍历过程中遇到的错误，遍历不会因此停止
+#[derive(Debug)]
+pub struct WalkError {
+    path: PathBuf,
+    depth: usize,
+    kind: WalkErrorKind,
+}
+
+#[derive(Debug)]
+enum WalkErrorKind {
+    Io(io::Error),
+    Loop { ancestor: PathBuf },
+}
+
+impl WalkError {
+    /// 出错的目录或者条目
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// 读取失败时的 IO 错误
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match &self.kind {
+            WalkErrorKind::Io(err) => Some(err),
+            WalkErrorKind::Loop { .. } => None,
+        }
+    }
+
+    /// 遇到符号链接循环时，被指向的那个祖先目录
+    pub fn loop_ancestor(&self) -> Option<&Path> {
+        match &self.kind {
+            WalkErrorKind::Io(_) => None,
+            WalkErrorKind::Loop { ancestor } => Some(ancestor),
+        }
+    }
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            WalkErrorKind::Io(err) => write!(f, "{}: {}", self.path.display(), err),
+            WalkErrorKind::Loop { ancestor } => write!(
+                f,
+                "{}: symlink loop back to {}",
+                self.path.display(),
+                ancestor.display()
+            ),
+        }
+    }
+}
+
+impl Error for WalkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            WalkErrorKind::Io(err) => Some(err),
+            WalkErrorKind::Loop { .. } => None,
+        }
+    }
+}
+
+/// 遍历到的条目组成的 Stream
+pub struct WalkDirStream {
+    /// 根目录的 visit，遍历完之后变成 None
+    driver: Option<BoxFuture<'static, ()>>,
+    rx: mpsc::Receiver<Result<DirEntry, WalkError>>,
+}
+
+impl Stream for WalkDirStream {
+    type Item = Result<DirEntry, WalkError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // 先推动遍历，让它往 channel 里放条目，channel 满了它就会停下来等待
+        if let Some(driver) = self.driver.as_mut() {
+            if driver.poll_unpin(cx).is_ready() {
+                self.driver = None;
+            }
+        }
+        // 遍历完成后 Walker 和其中的发送端被 drop，channel 中剩下的条目取完之后就返回 None
+        self.rx.poll_next_unpin(cx)
+    }
+}
+
+// 所有的 visit 共享的状态
+struct Walker {
+    follow_links: bool,
+    max_depth: usize,
+    open: Semaphore,
+    tx: mpsc::Sender<Result<DirEntry, WalkError>>,
+}
+
+// 读取一个目录得到的结果
+struct Listing {
+    /// 跟随符号链接时，这个目录规范化之后的路径
+    id: Option<PathBuf>,
+    entries: Vec<io::Result<RawEntry>>,
+}
+
+struct RawEntry {
+    path: PathBuf,
+    file_type: fs::FileType,
+
+    /// 是否是目录，跟随符号链接时也包括指向目录的符号链接
+    is_dir: bool,
+    id: Option<PathBuf>,
+}
+
+// `depth` 是 dir 本身的深度，`ancestors` 是从根目录到 dir 的父目录的规范化路径
+fn visit(
+    walker: Arc<Walker>,
+    dir: PathBuf,
+    depth: usize,
+    ancestors: Arc<Vec<PathBuf>>,
+) -> BoxFuture<'static, ()> {
+    async move {
+        let listing = {
+            let Ok(_permit) = walker.open.acquire().await else {
+                return;
+            };
+            let follow_links = walker.follow_links;
+            let dir = dir.clone();
+            unblock(move || read_listing(&dir, follow_links)).await
+        };
+
+        let listing = match listing {
+            Ok(listing) => listing,
+            Err(err) => {
+                let err = WalkError {
+                    path: dir,
+                    depth,
+                    kind: WalkErrorKind::Io(err),
+                };
+                let _ = walker.tx.send(Err(err)).await;
+                return;
+            }
+        };
+
+        let ancestors = match listing.id {
+            Some(id) => {
+                let mut chain = Vec::clone(&ancestors);
+                chain.push(id);
+                Arc::new(chain)
+            }
+            None => ancestors,
+        };
+
+        let child_depth = depth + 1;
+        let mut children = Vec::new();
+        for entry in listing.entries {
+            let item = match entry {
+                Ok(raw) => {
+                    if raw.is_dir && child_depth < walker.max_depth {
+                        match raw.id.as_ref().filter(|id| ancestors.contains(id)) {
+                            Some(ancestor) => {
+                                let err = WalkError {
+                                    path: raw.path.clone(),
+                                    depth: child_depth,
+                                    kind: WalkErrorKind::Loop {
+                                        ancestor: ancestor.clone(),
+                                    },
+                                };
+                                if walker.tx.send(Err(err)).await.is_err() {
+                                    return;
+                                }
+                            }
+                            None => children.push(visit(
+                                walker.clone(),
+                                raw.path.clone(),
+                                child_depth,
+                                ancestors.clone(),
+                            )),
+                        }
+                    }
+                    Ok(DirEntry {
+                        path: raw.path,
+                        depth: child_depth,
+                        file_type: raw.file_type,
+                    })
+                }
+                Err(err) => Err(WalkError {
+                    path: dir.clone(),
+                    depth,
+                    kind: WalkErrorKind::Io(err),
+                }),
+            };
+            // 接收端已经被 drop 了，不需要再遍历了
+            if walker.tx.send(item).await.is_err() {
+                return;
+            }
+        }
+
+        join_all(children).await;
+    }
+    .boxed()
+}
+
+// 在当前线程中读取一个目录，会阻塞
+fn read_listing(dir: &Path, follow_links: bool) -> io::Result<Listing> {
+    let id = if follow_links {
+        Some(fs::canonicalize(dir)?)
+    } else {
+        None
+    };
+
+    let entries = fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let (is_dir, id) = if file_type.is_dir() {
+                let id = if follow_links {
+                    fs::canonicalize(&path).ok()
+                } else {
+                    None
+                };
+                (true, id)
+            } else if file_type.is_symlink() && follow_links {
+                // 指向的目标不存在时就当作普通的条目
+                match fs::metadata(&path) {
+                    Ok(meta) if meta.is_dir() => (true, fs::canonicalize(&path).ok()),
+                    _ => (false, None),
+                }
+            } else {
+                (false, None)
+            };
+            Ok(RawEntry {
+                path,
+                file_type,
+                is_dir,
+                id,
+            })
+        })
+        .collect();
+
+    Ok(Listing { id, entries })
+}
+
+// 在一个新线程中运行会阻塞的 f，返回等待它的结果的 Future
+async fn unblock<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await.expect("blocking task panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    // 在临时目录下创建一棵目录树，测试结束时删除
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("async_q_07-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for dir in ["a/b/c", "d"] {
+                fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            for file in ["a/1.txt", "a/b/2.txt", "a/b/c/3.txt", "d/4.txt"] {
+                fs::write(root.join(file), file).unwrap();
+            }
+            TempTree(root)
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn collect(walk: WalkDir, root: &Path) -> (Vec<String>, Vec<WalkError>) {
+        let items: Vec<_> = block_on(walk.into_stream().collect());
+        let mut paths = Vec::new();
+        let mut errors = Vec::new();
+        for item in items {
+            match item {
+                Ok(entry) => paths.push(
+                    entry
+                        .path()
+                        .strip_prefix(root)
+                        .unwrap()
+                        .display()
+                        .to_string(),
+                ),
+                Err(err) => errors.push(err),
+            }
+        }
+        paths.sort();
+        (paths, errors)
+    }
+
+    #[test]
+    fn walks_whole_tree_and_respects_max_depth() {
+        let tree = TempTree::new("depth");
+        let (all, errors) = collect(WalkDir::new(&tree.0).max_open(2), &tree.0);
+        assert!(errors.is_empty());
+        assert_eq!(
+            all,
+            [
+                "a",
+                "a/1.txt",
+                "a/b",
+                "a/b/2.txt",
+                "a/b/c",
+                "a/b/c/3.txt",
+                "d",
+                "d/4.txt"
+            ]
+        );
+
+        let (shallow, _) = collect(WalkDir::new(&tree.0).max_depth(2), &tree.0);
+        assert_eq!(shallow, ["a", "a/1.txt", "a/b", "d", "d/4.txt"]);
+    }
+
+    #[test]
+    fn missing_root_is_reported_as_error() {
+        let missing = std::env::temp_dir().join("async_q_07-does-not-exist");
+        let (paths, errors) = collect(WalkDir::new(&missing), &missing);
+        assert!(paths.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].io_error().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_detected() {
+        let tree = TempTree::new("loop");
+        std::os::unix::fs::symlink(tree.0.join("a"), tree.0.join("a/b/up")).unwrap();
+
+        // 不跟随符号链接时 up 只是一个普通的条目
+        let (paths, errors) = collect(WalkDir::new(&tree.0), &tree.0);
+        assert!(paths.contains(&"a/b/up".to_string()));
+        assert!(errors.is_empty());
+
+        let (paths, errors) = collect(WalkDir::new(&tree.0).follow_links(true), &tree.0);
+        assert!(paths.contains(&"a/b/up".to_string()));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path(), tree.0.join("a/b/up"));
+        assert_eq!(
+            errors[0].loop_ancestor().unwrap(),
+            fs::canonicalize(tree.0.join("a")).unwrap()
+        );
+    }
+}
@@ -1,9 +1,18 @@
 
 use std::future::Future;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_q_07::service::{CircuitBreakerLayer, RetryLayer, Service, ServiceExt, TimeoutLayer};
 use async_std::io::prelude::*;
-use async_std::net;
+use async_std::net::{self, SocketAddr, ToSocketAddrs};
 use async_std::task;
+use futures::future::BoxFuture;
+use timer_future_02::cache::Cache;
+use timer_future_02::circuit_breaker::CircuitBreaker;
+use timer_future_02::rate_limit::{Keyed, RateLimiter, TokenBucket};
+use timer_future_02::retry::RetryPolicy;
 
 /// 异步函数以 async 开头
 /// 虽然返回值是 std::io::Result<String>，但无需调整返回值类型，Rust 自动把它当成相应的 Future 类型
@@ -26,7 +35,7 @@ use async_std::task;
 /// await 能干什么？：
 /// 1. 获得 Future 的所有权，并对其进行 poll
 /// 2. 对 Future 进行 poll 时，如果 Future 返回 Ready，其最终值就是 await 表达式，这时就继续执行后续代码，
-/// 否则就返回 Pending 给调用者
+///    否则就返回 Pending 给调用者
 /// 
 /// 
 /// Note：
@@ -49,18 +58,19 @@ use async_std::task;
 /// 而暂停执行时线程在做什么？它不是在干等着，而是在做其他的工作。
 ///
 /// 
-async fn cheapo_request(host: &str, port: u16, path: &str) -> std::io::Result<String> {
+/// 连接的地址由调用者解析好传进来（Client 会把 DNS 的结果缓存起来），host 只用来填 Host 头
+async fn cheapo_request(addrs: &[SocketAddr], host: &str, path: &str) -> std::io::Result<String> {
     /*
         .await 的是异步的
         .await 会等待，直到 Future 变成 ready，ready 后 await 最终会解析出 Future 的值
 
-        Note：当调用 async 函数时，在其函数体执行前，它就会立即返回，即执行到 port)) 函数就返回了
+        Note：当调用 async 函数时，在其函数体执行前，它就会立即返回，即执行到 connect(addrs) 函数就返回了
 
         这里就是获取 TcpStream::connect 返回 Future 的所有权，并对这个 Future 进行 poll，
         但对 Future 进行 poll，await 不是唯一的方式，其他的执行器也可以
 
      */
-    let mut socket = net::TcpStream::connect((host, port)).await?;
+    let mut socket = net::TcpStream::connect(addrs).await?;
     let request = format!("Get {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host);
 
     socket.write_all(request.as_bytes()).await?;
@@ -71,18 +81,112 @@ async fn cheapo_request(host: &str, port: u16, path: &str) -> std::io::Result<St
     Ok(response)
 }
 
+/// 连接被拒绝、被重置、超时这类错误过一会儿可能就好了，值得重试；
+/// 其他的错误（比如域名解析失败）重试也没有用
+fn is_transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | TimedOut
+            | Interrupted
+            | UnexpectedEof
+    )
+}
+
+/// cheapo_request 的参数，重试时要再用一次，所以是 Clone 的
+#[derive(Clone)]
+struct HttpRequest {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+/// 用 cheapo_request 发请求的客户端，写成 Service 后超时、重试这些都可以用 Layer 包在外面
+struct Client {
+    /// 对每个主机每秒最多发 5 个请求，重试也算在里面，免得把正在恢复的服务器又打垮
+    limits: Keyed<String, TokenBucket>,
+
+    /// DNS 的结果缓存 1 分钟，过期后的 5 分钟内先用旧的地址，同时在后台重新解析；
+    /// 解析失败不缓存，同一个主机同时只有一个解析在进行
+    dns: Cache<(String, u16), Vec<SocketAddr>>,
+}
+
+impl Client {
+    fn new() -> Self {
+        Client {
+            limits: Keyed::new(|| TokenBucket::new(5, Duration::from_secs(1))),
+            dns: Cache::new(256)
+                .ttl(Duration::from_secs(60))
+                .stale_while_revalidate(Duration::from_secs(300))
+                .spawner(|future| {
+                    task::spawn(future);
+                }),
+        }
+    }
+
+    async fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let host = host.to_string();
+        self.dns
+            .get_or_load((host.clone(), port), move || async move {
+                let addrs = (host.as_str(), port).to_socket_addrs().await?;
+                Ok(addrs.collect())
+            })
+            .await
+    }
+}
+
+impl Service<HttpRequest> for Client {
+    type Response = String;
+    type Error = std::io::Error;
+
+    fn call(&self, req: HttpRequest) -> BoxFuture<'_, std::io::Result<String>> {
+        Box::pin(async move {
+            self.limits.get(req.host.clone()).acquire().await;
+            let addrs = self.resolve(&req.host, req.port).await?;
+            cheapo_request(&addrs, &req.host, &req.path).await
+        })
+    }
+}
+
 fn main() -> std::io::Result<()> {
+    // 第一次连接失败时不直接退出，而是按指数退避（带随机抖动）重试，最多尝试 5 次、总共不超过 30 秒；
+    // 每一次尝试最多 10 秒，超时（TimedOut）也算是可以重试的错误
+    let policy = RetryPolicy::new().max_elapsed(Duration::from_secs(30));
+    // 上游连续失败时熔断，之后的请求直接失败，30 秒后再放一个请求过去试探
+    let breaker = Arc::new(CircuitBreaker::new().on_state_change(|old, new| {
+        eprintln!("circuit breaker: {old:?} -> {new:?}");
+    }));
+    let client = Client::new()
+        .layer(TimeoutLayer::new(Duration::from_secs(10)))
+        .layer(CircuitBreakerLayer::new(breaker))
+        .layer(RetryLayer::new(policy, is_transient));
+    let request = HttpRequest {
+        host: "example.com".to_string(),
+        port: 80,
+        path: "/".to_string(),
+    };
+
     // 在非异步函数中调用异步函数 block_on 是一种方式, 它是一个执行器
-    let response = task::block_on(cheapo_request("example.com", 80, "/"))?;
+    let response = task::block_on(async {
+        client.ready().await?;
+        client.call(request).await
+    })?;
     println!("{}", response);
     Ok(())
 }
 
 
 // This function:
+#[allow(dead_code)]
 async fn foo(x: &u8) -> u8 { *x }
 
 // Is equivalent to this function
+#[allow(dead_code, clippy::manual_async_fn)]
 fn foo_expanded<'a>(x: &'a u8) -> impl Future<Output = u8> + 'a {
     async move { *x }
 }
\ No newline at end of file
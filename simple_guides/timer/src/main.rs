@@ -52,5 +52,5 @@ async fn main() {
         println!("{:?}", file2_contents);
     });
 
-    let _ = tokio::join!(h1, h2);
+    let _ = async_demo_01::join!(h1, h2);
 }
@@ -0,0 +1,478 @@
+/*
+    带过期时间的异步缓存
+
+    let cache = Cache::new(1024).ttl(Duration::from_secs(60));
+    let addrs = cache.get_or_load(host.clone(), move || resolve(host)).await?;
+
+    - 每个条目有自己的过期时间，get_or_load_with_ttl 的加载函数可以为每个值指定（比如 DNS 记录的 TTL）
+    - 超过 capacity 时淘汰最久没有被用过的条目（LRU）
+    - 同一个 key 同时有很多任务没有命中时，只有一个加载函数在运行，其他的任务等它的结果；
+      和 OnceCell 一样，加载失败时不缓存，排在后面的任务用自己的加载函数再试一次
+    - stale_while_revalidate：过期后的一段时间内，直接返回旧的值，同时在后台重新加载，
+      这样热点条目过期的那一刻请求也不用等。后台加载需要用 spawner 传入生成任务的函数，
+      没有 spawner 时过期的条目就当作没有命中
+
+    用 Mutex 保护的状态只在查找、插入的时候短暂地锁一下，加载是在锁外面进行的，
+    同一个 key 的加载者用一个 Semaphore(1) 排队
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+
+use crate::sync::Semaphore;
+
+type Spawn = Box<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>;
+
+/// 异步缓存，clone 出来的都指向同一份数据
+pub struct Cache<K, V> {
+    inner: Arc<Inner<K, V>>,
+}
+
+struct Inner<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    stale: Duration,
+    spawn: Option<Spawn>,
+    state: Mutex<State<K, V>>,
+}
+
+struct State<K, V> {
+    entries: HashMap<K, Entry<V>>,
+
+    /// 使用的先后顺序：tick -> key，最前面的是最久没有用过的
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+
+    /// 正在加载的 key，同一个 key 的加载者在它的信号量上排队
+    loading: HashMap<K, Arc<Semaphore>>,
+
+    /// 正在后台重新加载的 key，免得每次命中旧值都再启动一个
+    refreshing: HashSet<K>,
+}
+
+struct Entry<V> {
+    value: V,
+    expires: Instant,
+    tick: u64,
+}
+
+enum Lookup<V> {
+    Fresh(V),
+    Stale(V),
+    Miss,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> State<K, V> {
+    fn lookup(&mut self, key: &K, now: Instant, stale: Duration) -> Lookup<V> {
+        let Some(entry) = self.entries.get(key) else {
+            return Lookup::Miss;
+        };
+        let (fresh, usable) = (now < entry.expires, now < entry.expires + stale);
+        if !usable {
+            self.remove(key);
+            return Lookup::Miss;
+        }
+        let value = entry.value.clone();
+        self.touch(key);
+        if fresh {
+            Lookup::Fresh(value)
+        } else {
+            Lookup::Stale(value)
+        }
+    }
+
+    // 标记为刚刚用过
+    fn touch(&mut self, key: &K) {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key).unwrap();
+        self.recency.remove(&entry.tick);
+        entry.tick = self.tick;
+        self.recency.insert(self.tick, key.clone());
+    }
+
+    fn insert(&mut self, key: K, value: V, expires: Instant, capacity: usize) {
+        self.remove(&key);
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires,
+                tick: self.tick,
+            },
+        );
+        while self.entries.len() > capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.tick);
+        Some(entry.value)
+    }
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// 最多保存 `capacity` 个条目，默认的 TTL 是 60 秒
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "cache capacity must be at least one");
+        Cache {
+            inner: Arc::new(Inner {
+                capacity,
+                ttl: Duration::from_secs(60),
+                stale: Duration::ZERO,
+                spawn: None,
+                state: Mutex::new(State {
+                    entries: HashMap::new(),
+                    recency: BTreeMap::new(),
+                    tick: 0,
+                    loading: HashMap::new(),
+                    refreshing: HashSet::new(),
+                }),
+            }),
+        }
+    }
+
+    /// insert、get_or_load 使用的默认 TTL
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.configure().ttl = ttl;
+        self
+    }
+
+    /// 过期之后的 `grace` 这么长的时间内返回旧的值，同时在后台重新加载
+    pub fn stale_while_revalidate(mut self, grace: Duration) -> Self {
+        self.configure().stale = grace;
+        self
+    }
+
+    /// 后台重新加载时用 `spawn` 生成任务，比如 `|future| { async_std::task::spawn(future); }`
+    pub fn spawner(
+        mut self,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> Self {
+        self.configure().spawn = Some(Box::new(spawn));
+        self
+    }
+
+    fn configure(&mut self) -> &mut Inner<K, V> {
+        Arc::get_mut(&mut self.inner).expect("cache must be configured before it is cloned")
+    }
+
+    /// 没有过期的值
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut state = self.inner.state.lock().unwrap();
+        // 过期了但还在 grace 中的条目留着，后台加载失败时还要用它
+        match state.lookup(key, Instant::now(), self.inner.stale) {
+            Lookup::Fresh(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_with_ttl(key, value, self.inner.ttl);
+    }
+
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let expires = Instant::now() + ttl;
+        let mut state = self.inner.state.lock().unwrap();
+        state.insert(key, value, expires, self.inner.capacity);
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.inner.state.lock().unwrap().remove(key)
+    }
+
+    /// 包括已经过期、还没有被清理掉的条目
+    pub fn len(&self) -> usize {
+        self.inner.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 返回缓存的值，没有命中时用 `load` 加载，加载的值使用默认的 TTL
+    pub async fn get_or_load<F, Fut, E>(&self, key: K, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let ttl = self.inner.ttl;
+        self.get_or_load_with_ttl(key, move || {
+            let loading = load();
+            async move { loading.await.map(|value| (value, ttl)) }
+        })
+        .await
+    }
+
+    /// 和 get_or_load 一样，只是 `load` 同时返回这个值的 TTL
+    pub async fn get_or_load_with_ttl<F, Fut, E>(&self, key: K, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(V, Duration), E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        // 没有 spawner 时没法在后台加载，过期的条目都当作没有命中
+        let stale = match self.inner.spawn {
+            Some(_) => self.inner.stale,
+            None => Duration::ZERO,
+        };
+        let (lock, refresh) = {
+            let mut state = self.inner.state.lock().unwrap();
+            let refresh = match state.lookup(&key, Instant::now(), stale) {
+                Lookup::Fresh(value) => return Ok(value),
+                Lookup::Stale(value) => {
+                    if !state.refreshing.insert(key.clone()) {
+                        return Ok(value);
+                    }
+                    Some(value)
+                }
+                Lookup::Miss => None,
+            };
+            let lock = state
+                .loading
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new(Semaphore::new(1)))
+                .clone();
+            (lock, refresh)
+        };
+
+        // 在交给 spawner 之前就创建好 guard，spawner 没有 poll 就把 Future drop 了也能清理
+        let guard = LoadGuard {
+            cache: self.clone(),
+            key,
+            lock: Some(lock),
+            refresh: refresh.is_some(),
+        };
+        match refresh {
+            Some(value) => {
+                let refreshing = async move {
+                    // 失败时旧的值继续使用到 grace 结束，下一次命中旧值时再试
+                    let _ = guard.load(load).await;
+                };
+                (self.inner.spawn.as_ref().unwrap())(Box::pin(refreshing));
+                Ok(value)
+            }
+            None => guard.load(load).await,
+        }
+    }
+}
+
+// 加载结束（完成、失败或者被取消）时，没有人再排队的话从 loading 中删掉这个 key
+struct LoadGuard<K: Hash + Eq, V> {
+    cache: Cache<K, V>,
+    key: K,
+    lock: Option<Arc<Semaphore>>,
+    refresh: bool,
+}
+
+impl<K, V> LoadGuard<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    async fn load<F, Fut, E>(self, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(V, Duration), E>>,
+    {
+        // 信号量从来不会被关闭
+        let _permit = self.lock.as_ref().unwrap().acquire().await.unwrap();
+        // 排队的时候前面的加载者可能已经加载好了
+        if let Some(value) = self.cache.get(&self.key) {
+            return Ok(value);
+        }
+        let (value, ttl) = load().await?;
+        self.cache
+            .insert_with_ttl(self.key.clone(), value.clone(), ttl);
+        Ok(value)
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for LoadGuard<K, V> {
+    fn drop(&mut self) {
+        let mut state = self.cache.inner.state.lock().unwrap();
+        drop(self.lock.take());
+        if state
+            .loading
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            state.loading.remove(&self.key);
+        }
+        if self.refresh {
+            state.refreshing.remove(&self.key);
+        }
+    }
+}
+
+impl<K, V> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Cache {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Cache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.state.lock().unwrap();
+        f.debug_struct("Cache")
+            .field("len", &state.entries.len())
+            .field("capacity", &self.inner.capacity)
+            .field("loading", &state.loading.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel::oneshot, test_util::poll_once};
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn misses_queue_per_key_and_a_cancelled_loader_hands_over() {
+        let cache = Cache::new(8);
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        // 第一个加载者卡住，同一个 key 的第二个排在它后面
+        let (_tx, rx) = oneshot::channel::<u32>();
+        let stuck = move || async move { Ok::<_, ()>(rx.await.unwrap()) };
+        let mut first = Box::pin(cache.get_or_load("slow", stuck));
+        let mut second = Box::pin(cache.get_or_load("slow", {
+            let loads = loads.clone();
+            move || async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>(2)
+            }
+        }));
+        assert!(poll_once(&mut first).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+
+        // 别的 key 不用等
+        assert_eq!(
+            block_on(cache.get_or_load("fast", || async { Ok::<_, ()>(1) })),
+            Ok(1)
+        );
+
+        // 第一个被取消后，排队的用自己的加载函数接着加载
+        drop(first);
+        assert_eq!(block_on(second), Ok(2));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.get(&"slow"), Some(2));
+        assert!(cache.inner.state.lock().unwrap().loading.is_empty());
+
+        let failed = block_on(cache.get_or_load("missing", || async { Err::<u32, _>("boom") }));
+        assert_eq!(failed, Err("boom"));
+        assert_eq!(cache.get(&"missing"), None);
+    }
+
+    #[test]
+    fn entries_expire_and_least_recently_used_are_evicted() {
+        let cache = Cache::new(2).ttl(Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+        // b 最久没有用过
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.len(), 2);
+
+        cache.insert_with_ttl("short", 4, Duration::from_millis(20));
+        assert_eq!(cache.get(&"short"), Some(4));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&"short"), None);
+
+        let value = block_on(cache.get_or_load_with_ttl("dns", || async {
+            Ok::<_, ()>((5, Duration::from_millis(20)))
+        }));
+        assert_eq!(value, Ok(5));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&"dns"), None);
+    }
+
+    #[test]
+    fn stale_values_are_served_while_refreshing_in_background() {
+        let cache = Cache::new(8)
+            .stale_while_revalidate(Duration::from_secs(60))
+            .spawner(|future| {
+                std::thread::spawn(move || block_on(future));
+            });
+        let version = Arc::new(AtomicUsize::new(0));
+        let load = |version: &Arc<AtomicUsize>| {
+            let version = version.clone();
+            move || async move {
+                // 后台加载慢一点，好确认旧值是立刻返回的
+                crate::time::sleep(Duration::from_millis(20)).await;
+                let version = version.fetch_add(1, Ordering::SeqCst);
+                // 第一个版本很快过期，之后的不会
+                let ttl = if version == 0 { 20 } else { 60_000 };
+                Ok::<_, ()>((version, Duration::from_millis(ttl)))
+            }
+        };
+
+        assert_eq!(
+            block_on(cache.get_or_load_with_ttl("page", load(&version))),
+            Ok(0)
+        );
+        std::thread::sleep(Duration::from_millis(30));
+        let start = Instant::now();
+        assert_eq!(
+            block_on(cache.get_or_load_with_ttl("page", load(&version))),
+            Ok(0)
+        );
+        assert_eq!(
+            block_on(cache.get_or_load_with_ttl("page", load(&version))),
+            Ok(0)
+        );
+        assert!(start.elapsed() < Duration::from_millis(20));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(cache.get(&"page"), Some(1));
+        // 第二次命中旧值时后台已经在加载了，没有再启动一个
+        assert_eq!(version.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn refresh_dropped_by_the_spawner_does_not_block_later_refreshes() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let cache = Cache::new(8)
+            .stale_while_revalidate(Duration::from_secs(60))
+            .spawner({
+                let spawned = spawned.clone();
+                // 比如运行时已经关闭了，Future 没有被 poll 就被 drop 了
+                move |future| {
+                    spawned.fetch_add(1, Ordering::SeqCst);
+                    drop(future);
+                }
+            });
+        cache.insert_with_ttl("page", 1, Duration::ZERO);
+
+        for _ in 0..2 {
+            let value = block_on(cache.get_or_load("page", || async { Ok::<_, ()>(2) }));
+            assert_eq!(value, Ok(1));
+        }
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        let state = cache.inner.state.lock().unwrap();
+        assert!(state.loading.is_empty());
+        assert!(state.refreshing.is_empty());
+    }
+}
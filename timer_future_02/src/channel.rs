@@ -0,0 +1,22 @@
+/*
+    异步 channel
+
+    main.rs 中的执行者用的是标准库的 `sync_channel`，它的 `recv()` 会阻塞线程，
+    只适合执行者内部用来传递任务，任务之间没法用它来 `.await` 消息。
+
+    这里的几种 channel 都只依赖 `Waker`：
+    1. 接收端（或等待容量的发送端）在没有进展时把 `cx.waker()` 存到共享状态里，然后返回 Pending
+    2. 另一端产生进展（发送了值、被 drop 等）时取出 waker 并调用 wake()
+
+    因为不依赖任何运行时的 API，所以同样的代码可以运行在 main.rs 中的执行者、async-std 和 tokio 上
+
+    - oneshot：只发送一个值
+    - mpsc：多生产者、单消费者，有界（bounded）和无界（unbounded）两种
+    - broadcast：每个接收者都能收到每个值，接收者落后太多时会收到 `Lagged` 错误
+    - watch：只保存最新的值，接收者可以等待“值发生了变化”
+*/
+
+pub mod broadcast;
+pub mod mpsc;
+pub mod oneshot;
+pub mod watch;
@@ -0,0 +1,320 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures::Stream;
+
+/// 创建一个 broadcast channel，每个接收者都会收到每一个值
+///
+/// 内部是一个容量为 `capacity` 的环形缓冲区，发送永远不会等待；
+/// 缓冲区满了就覆盖最旧的值，还没读到这些值的接收者下次接收时会得到 `RecvError::Lagged`
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast channel requires capacity > 0");
+    let shared = Arc::new(Mutex::new(State {
+        buffer: VecDeque::with_capacity(capacity),
+        capacity,
+        head: 0,
+        senders: 1,
+        receivers: 1,
+        rx_wakers: HashMap::new(),
+        next_rx_id: 1,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            next: 0,
+            id: 0,
+        },
+    )
+}
+
+/// 发送端，可以 clone
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+/// 接收端，每个接收者独立记录自己读到了哪里
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+
+    /// 下一个要读的位置（从 channel 创建开始的全局序号）
+    next: u64,
+
+    /// 用来在 `rx_wakers` 中找到自己的 waker
+    id: u64,
+}
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    capacity: usize,
+
+    /// buffer 中第一个值的全局序号，
+    /// 所以 buffer 中的值的序号是 head..head + buffer.len()
+    head: u64,
+
+    senders: usize,
+    receivers: usize,
+
+    /// 正在等待新值的接收者
+    rx_wakers: HashMap<u64, Waker>,
+    next_rx_id: u64,
+}
+
+impl<T> State<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+}
+
+/// 所有接收者都已经 drop 了
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// 接收失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// 所有发送端都 drop 了，并且已经读完了缓冲区
+    Closed,
+    /// 接收者落后太多，有这么多个值被覆盖掉了；接收者会跳到最旧的还在的值继续读
+    Lagged(u64),
+}
+
+/// `try_recv` 失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+impl<T: Clone> Sender<T> {
+    /// 发送一个值，返回能收到它的接收者数量
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.shared.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == state.capacity {
+            // 覆盖最旧的值
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        for (_, waker) in state.rx_wakers.drain() {
+            waker.wake();
+        }
+        Ok(state.receivers)
+    }
+
+    /// 创建一个新的接收者，它只会收到之后发送的值
+    pub fn subscribe(&self) -> Receiver<T> {
+        new_receiver(&self.shared)
+    }
+
+    /// 当前活着的接收者数量
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().unwrap().receivers
+    }
+}
+
+fn new_receiver<T>(shared: &Arc<Mutex<State<T>>>) -> Receiver<T> {
+    let mut state = shared.lock().unwrap();
+    state.receivers += 1;
+    let id = state.next_rx_id;
+    state.next_rx_id += 1;
+    Receiver {
+        shared: shared.clone(),
+        next: state.tail(),
+        id,
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().unwrap().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            for (_, waker) in state.rx_wakers.drain() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// 接收下一个值
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { rx: self }
+    }
+
+    /// 不等待，直接读取下一个值
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.shared.lock().unwrap();
+        match Self::read(&mut self.next, &state) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError::Lagged(n))) => Err(TryRecvError::Lagged(n)),
+            Some(Err(RecvError::Closed)) => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// 在手写的 Future 中接收值
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let mut state = self.shared.lock().unwrap();
+        match Self::read(&mut self.next, &state) {
+            Some(result) => {
+                state.rx_wakers.remove(&self.id);
+                Poll::Ready(result)
+            }
+            None => {
+                state.rx_wakers.insert(self.id, cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// 创建一个从“现在”开始接收的新接收者
+    pub fn resubscribe(&self) -> Self {
+        new_receiver(&self.shared)
+    }
+
+    // 返回 None 表示暂时没有新值，需要等待
+    fn read(next: &mut u64, state: &State<T>) -> Option<Result<T, RecvError>> {
+        if *next < state.head {
+            let missed = state.head - *next;
+            *next = state.head;
+            return Some(Err(RecvError::Lagged(missed)));
+        }
+        if *next < state.tail() {
+            let value = state.buffer[(*next - state.head) as usize].clone();
+            *next += 1;
+            return Some(Ok(value));
+        }
+        if state.senders == 0 {
+            return Some(Err(RecvError::Closed));
+        }
+        None
+    }
+}
+
+/// 作为 Stream 时，`Lagged` 会作为一个 Err 元素交给使用者，`Closed` 则表示 Stream 结束
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = Result<T, RecvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut().poll_recv(cx) {
+            Poll::Ready(Err(RecvError::Closed)) => Poll::Ready(None),
+            Poll::Ready(result) => Poll::Ready(Some(result)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.receivers -= 1;
+        state.rx_wakers.remove(&self.id);
+    }
+}
+
+/// `Receiver::recv` 返回的 Future
+pub struct Recv<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<T: Clone> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "broadcast channel has no receivers")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Closed => write!(f, "broadcast channel closed"),
+            RecvError::Lagged(n) => write!(f, "broadcast receiver lagged by {n} messages"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "broadcast channel is empty"),
+            TryRecvError::Closed => write!(f, "broadcast channel closed"),
+            TryRecvError::Lagged(n) => write!(f, "broadcast receiver lagged by {n} messages"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn every_receiver_sees_every_value() {
+        let (tx, mut rx1) = channel(4);
+        let mut rx2 = tx.subscribe();
+        assert_eq!(tx.send(1), Ok(2));
+        tx.send(2).unwrap();
+        drop(tx);
+        block_on(async {
+            assert_eq!(rx1.recv().await, Ok(1));
+            assert_eq!(rx1.recv().await, Ok(2));
+            assert_eq!(rx1.recv().await, Err(RecvError::Closed));
+            assert_eq!(rx2.recv().await, Ok(1));
+        });
+    }
+
+    #[test]
+    fn slow_receiver_is_told_it_lagged() {
+        let (tx, mut rx) = channel(2);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures::Stream;
+
+/// 创建一个有界的 mpsc channel，最多缓存 `buffer` 个值
+///
+/// 缓冲区满了之后，`Sender::send` 返回的 Future 会 Pending，直到接收者取走了值
+pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(buffer > 0, "mpsc bounded channel requires buffer > 0");
+    let chan = Chan::new(Some(buffer));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// 创建一个无界的 mpsc channel，发送永远不会等待
+pub fn unbounded<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let chan = Chan::new(None);
+    (
+        UnboundedSender { chan: chan.clone() },
+        UnboundedReceiver(Receiver { chan }),
+    )
+}
+
+/// 有界 channel 的发送端，可以 clone 出多个
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+/// 无界 channel 的发送端，可以 clone 出多个
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+/// 接收端，只能有一个
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+/// 无界 channel 的接收端，用法和 `Receiver` 一样
+pub struct UnboundedReceiver<T>(Receiver<T>);
+
+struct Chan<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+
+    /// None 表示无界
+    capacity: Option<usize>,
+
+    /// 还活着的发送端数量，降为 0 时 channel 就关闭了
+    senders: usize,
+
+    /// 接收端被 drop 了或调用了 `close`
+    rx_closed: bool,
+
+    /// 接收任务的 waker
+    rx_waker: Option<Waker>,
+
+    /// 因为缓冲区满而等待的发送任务，按先来后到排队
+    send_waiters: VecDeque<(u64, Waker)>,
+    next_waiter_id: u64,
+}
+
+/// 接收端已经关闭，值原样还回去
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// `try_send` 失败的原因，都会把值还回去
+#[derive(PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// 缓冲区满了
+    Full(T),
+    /// 接收端已经关闭
+    Closed(T),
+}
+
+/// `try_recv` 失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// 暂时没有值
+    Empty,
+    /// 所有发送端都 drop 了，并且队列已经空了
+    Disconnected,
+}
+
+impl<T> Chan<T> {
+    fn new(capacity: Option<usize>) -> Arc<Self> {
+        Arc::new(Chan {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity,
+                senders: 1,
+                rx_closed: false,
+                rx_waker: None,
+                send_waiters: VecDeque::new(),
+                next_waiter_id: 0,
+            }),
+        })
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.state.lock().unwrap();
+        if state.rx_closed {
+            return Err(TrySendError::Closed(value));
+        }
+        if let Some(capacity) = state.capacity {
+            if state.queue.len() >= capacity {
+                return Err(TrySendError::Full(value));
+            }
+        }
+        state.queue.push_back(value);
+        if let Some(waker) = state.rx_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Self> {
+        self.state.lock().unwrap().senders += 1;
+        self.clone()
+    }
+
+    fn drop_sender(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // 最后一个发送端没了，唤醒接收者让它看到 channel 已关闭
+            if let Some(waker) = state.rx_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// 发送一个值，缓冲区满时等待，直到有空位
+    ///
+    /// 返回的 Future 在完成前被 drop 的话，值不会被发送（也就是 cancel-safe 的）
+    pub fn send(&self, value: T) -> Send<'_, T> {
+        Send {
+            chan: &self.chan,
+            value: Some(value),
+            waiter: None,
+        }
+    }
+
+    /// 不等待，缓冲区满了就直接失败
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(value)
+    }
+
+    /// 接收端是否已经关闭
+    pub fn is_closed(&self) -> bool {
+        self.chan.state.lock().unwrap().rx_closed
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> UnboundedSender<T> {
+    /// 发送一个值，无界 channel 不需要等待
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan.try_send(value).map_err(|err| match err {
+            TrySendError::Closed(value) | TrySendError::Full(value) => SendError(value),
+        })
+    }
+
+    /// 接收端是否已经关闭
+    pub fn is_closed(&self) -> bool {
+        self.chan.state.lock().unwrap().rx_closed
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// `Sender::send` 返回的 Future
+pub struct Send<'a, T> {
+    chan: &'a Chan<T>,
+    value: Option<T>,
+
+    /// 在等待队列中的编号，None 表示没有在排队
+    waiter: Option<u64>,
+}
+
+// Send 里没有自引用的字段，可以安全地 Unpin
+impl<T> Unpin for Send<'_, T> {}
+
+impl<T> Future for Send<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.chan.state.lock().unwrap();
+        let value = this.value.take().expect("Send polled after completion");
+
+        if state.rx_closed {
+            return Poll::Ready(Err(SendError(value)));
+        }
+
+        let capacity = state.capacity.unwrap_or(usize::MAX);
+        if state.queue.len() < capacity {
+            if let Some(id) = this.waiter.take() {
+                state.send_waiters.retain(|(waiter_id, _)| *waiter_id != id);
+            }
+            state.queue.push_back(value);
+            if let Some(waker) = state.rx_waker.take() {
+                waker.wake();
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        // 缓冲区满了，排队等待接收者腾出空位
+        this.value = Some(value);
+        let waker = cx.waker().clone();
+        match this.waiter {
+            Some(id) => {
+                match state.send_waiters.iter_mut().find(|(waiter_id, _)| *waiter_id == id) {
+                    Some(entry) => entry.1 = waker,
+                    // 被唤醒过但空位又被别人抢走了，重新排到队首
+                    None => state.send_waiters.push_front((id, waker)),
+                }
+            }
+            None => {
+                let id = state.next_waiter_id;
+                state.next_waiter_id += 1;
+                state.send_waiters.push_back((id, waker));
+                this.waiter = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Send<'_, T> {
+    fn drop(&mut self) {
+        let Some(id) = self.waiter else { return };
+        let mut state = self.chan.state.lock().unwrap();
+        let before = state.send_waiters.len();
+        state.send_waiters.retain(|(waiter_id, _)| *waiter_id != id);
+        // 不在队列里说明已经被唤醒了，但还没来得及发送就被取消了，
+        // 把这次唤醒让给下一个等待者，否则空位会一直空着
+        if state.send_waiters.len() == before && self.value.is_some() {
+            if let Some((_, waker)) = state.send_waiters.pop_front() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 接收下一个值，所有发送端都 drop 且队列为空时返回 None
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { rx: self }
+    }
+
+    /// 在手写的 Future 中接收值
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.chan.state.lock().unwrap();
+        if let Some(value) = state.queue.pop_front() {
+            // 腾出了一个空位，唤醒排在最前面的发送者
+            if let Some((_, waker)) = state.send_waiters.pop_front() {
+                waker.wake();
+            }
+            Poll::Ready(Some(value))
+        } else if state.senders == 0 {
+            Poll::Ready(None)
+        } else {
+            state.rx_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// 不等待，直接取一个值
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.chan.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(value) => {
+                if let Some((_, waker)) = state.send_waiters.pop_front() {
+                    waker.wake();
+                }
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// 关闭 channel：之后的发送都会失败，已经在队列里的值仍然可以取出
+    pub fn close(&mut self) {
+        let mut state = self.chan.state.lock().unwrap();
+        state.rx_closed = true;
+        for (_, waker) in state.send_waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> UnboundedReceiver<T> {
+    /// 接收下一个值，所有发送端都 drop 且队列为空时返回 None
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        self.0.recv()
+    }
+
+    /// 在手写的 Future 中接收值
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.0.poll_recv(cx)
+    }
+
+    /// 不等待，直接取一个值
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// 关闭 channel，之后的发送都会失败
+    pub fn close(&mut self) {
+        self.0.close()
+    }
+}
+
+impl<T> Stream for UnboundedReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().0.poll_recv(cx)
+    }
+}
+
+/// `Receiver::recv` 返回的 Future
+pub struct Recv<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mpsc receiver closed")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Full(..)"),
+            TrySendError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "mpsc channel is full"),
+            TrySendError::Closed(_) => write!(f, "mpsc receiver closed"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "mpsc channel is empty"),
+            TryRecvError::Disconnected => write!(f, "all mpsc senders dropped"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::thread;
+
+    #[test]
+    fn bounded_send_waits_for_capacity() {
+        let (tx, mut rx) = channel(2);
+        let producer = thread::spawn(move || {
+            block_on(async {
+                for i in 0..10 {
+                    tx.send(i).await.unwrap();
+                }
+            })
+        });
+        let received: Vec<i32> = block_on(async {
+            let mut out = Vec::new();
+            while let Some(v) = rx.recv().await {
+                out.push(v);
+            }
+            out
+        });
+        producer.join().unwrap();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn try_send_reports_full() {
+        let (tx, mut rx) = channel(1);
+        tx.try_send(1).unwrap();
+        assert!(matches!(tx.try_send(2), Err(TrySendError::Full(2))));
+        assert_eq!(rx.try_recv(), Ok(1));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn unbounded_receiver_is_a_stream() {
+        let (tx, rx) = unbounded();
+        let tx2 = tx.clone();
+        tx.send("a").unwrap();
+        tx2.send("b").unwrap();
+        drop((tx, tx2));
+        assert_eq!(block_on(rx.collect::<Vec<_>>()), vec!["a", "b"]);
+    }
+}
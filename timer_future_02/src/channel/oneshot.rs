@@ -0,0 +1,209 @@
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// 创建一个只能发送一个值的 channel
+///
+/// `Receiver` 本身就是一个 Future，`.await` 它就能拿到发送的值
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value: None,
+        rx_waker: None,
+        tx_dropped: false,
+        rx_dropped: false,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// 发送端，`send` 会消耗掉自己，所以最多只能发送一次
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+/// 接收端，作为 Future 完成时返回发送的值
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+// 在 Sender 和 Receiver 之间共享的状态
+struct State<T> {
+    /// 已经发送、但还没有被取走的值
+    value: Option<T>,
+
+    /// 最后一次 poll `Receiver` 的任务的 waker，值到达或 Sender 被 drop 时用它唤醒接收的任务
+    rx_waker: Option<Waker>,
+
+    tx_dropped: bool,
+    rx_dropped: bool,
+}
+
+/// Sender 在发送之前就被 drop 了，或者接收端在收到值之前调用了 `close`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// `try_recv` 失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// 还没有值，但 Sender 还在
+    Empty,
+    /// Sender 没有发送就被 drop 了，或者调用过 `close`
+    Closed,
+}
+
+impl<T> Sender<T> {
+    /// 发送值并唤醒接收的任务
+    ///
+    /// 如果 Receiver 已经被 drop，就把值原样还回去
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.shared.lock().unwrap();
+        if state.rx_dropped {
+            return Err(value);
+        }
+        state.value = Some(value);
+        if let Some(waker) = state.rx_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Receiver 是否已经被 drop 了（或调用了 `close`）
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().unwrap().rx_dropped
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.tx_dropped = true;
+        // 不管有没有发送值，都要唤醒接收者：有值就取值，没值就返回 RecvError
+        if let Some(waker) = state.rx_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 不等待，直接看看是否已经有值了
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock().unwrap();
+        match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.tx_dropped || state.rx_dropped => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// 关闭接收端，之后 `send` 都会失败，但已经发送的值仍然可以取出；
+    /// 没有值时 `.await` 直接返回 RecvError，不会一直等下去
+    pub fn close(&mut self) {
+        self.shared.lock().unwrap().rx_dropped = true;
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(Ok(value))
+        } else if state.tx_dropped || state.rx_dropped {
+            // rx_dropped 只可能是调用了 close，之后不会再有值了
+            Poll::Ready(Err(RecvError))
+        } else {
+            // 和 TimerFuture 一样，每次 poll 都更新 waker，因为 Receiver 可能在任务之间移动
+            match &state.rx_waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => state.rx_waker = Some(cx.waker().clone()),
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.rx_dropped = true;
+        state.rx_waker = None;
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "oneshot sender dropped without sending")
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "oneshot channel is empty"),
+            TryRecvError::Closed => write!(f, "oneshot sender dropped without sending"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn receives_value_sent_from_another_thread() {
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(42).unwrap();
+        });
+        assert_eq!(block_on(rx), Ok(42));
+    }
+
+    #[test]
+    fn dropped_sender_closes_channel() {
+        let (tx, rx) = channel::<u8>();
+        drop(tx);
+        assert_eq!(block_on(rx), Err(RecvError));
+    }
+
+    #[test]
+    fn send_fails_after_receiver_dropped() {
+        let (tx, rx) = channel();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send("hi"), Err("hi"));
+    }
+
+    #[test]
+    fn closed_receiver_returns_sent_value_or_error() {
+        let (tx, mut rx) = channel();
+        tx.send(1).unwrap();
+        rx.close();
+        assert_eq!(block_on(rx), Ok(1));
+
+        // Sender 还在，但已经不能发送了，不能一直等下去
+        let (tx, mut rx) = channel::<u8>();
+        rx.close();
+        assert!(tx.is_closed());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+        assert_eq!(block_on(rx), Err(RecvError));
+        drop(tx);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use futures::Stream;
+
+/// 创建一个 watch channel，初始值为 `init`
+///
+/// channel 中只保存最新的一个值，接收者可以随时 `borrow()` 当前值，
+/// 也可以 `changed().await` 等待值被更新
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            value: init,
+            version: 0,
+            sender_dropped: false,
+            receivers: 1,
+            rx_wakers: HashMap::new(),
+            next_rx_id: 1,
+        }),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            seen_version: 0,
+            id: 0,
+        },
+    )
+}
+
+/// 发送端，只有一个
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// 接收端，可以 clone，每个接收者独立记录自己看到的版本
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    seen_version: u64,
+    id: u64,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    value: T,
+
+    /// 每次发送都加 1，接收者比较版本号就知道值有没有变
+    version: u64,
+
+    sender_dropped: bool,
+    receivers: usize,
+    rx_wakers: HashMap<u64, Waker>,
+    next_rx_id: u64,
+}
+
+/// `borrow()` 返回的只读引用，持有期间会占用 channel 的锁，所以不要跨 `.await` 持有它
+pub struct Ref<'a, T> {
+    guard: MutexGuard<'a, State<T>>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard.value
+    }
+}
+
+/// 所有接收者都 drop 了，值原样还回去
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Sender 已经被 drop 了，不会再有新值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl<T> Shared<T> {
+    fn notify_receivers(state: &mut State<T>) {
+        for (_, waker) in state.rx_wakers.drain() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// 更新值并通知所有接收者；没有接收者时返回 Err
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        state.value = value;
+        state.version += 1;
+        Shared::notify_receivers(&mut state);
+        Ok(())
+    }
+
+    /// 不管有没有接收者都更新值，返回旧值
+    pub fn send_replace(&self, value: T) -> T {
+        let mut state = self.shared.state.lock().unwrap();
+        let old = std::mem::replace(&mut state.value, value);
+        state.version += 1;
+        Shared::notify_receivers(&mut state);
+        old
+    }
+
+    /// 原地修改值并通知所有接收者
+    pub fn send_modify<F: FnOnce(&mut T)>(&self, modify: F) {
+        let mut state = self.shared.state.lock().unwrap();
+        modify(&mut state.value);
+        state.version += 1;
+        Shared::notify_receivers(&mut state);
+    }
+
+    /// 查看当前值
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            guard: self.shared.state.lock().unwrap(),
+        }
+    }
+
+    /// 创建一个新的接收者，当前值对它来说算是“已经看过的”
+    pub fn subscribe(&self) -> Receiver<T> {
+        new_receiver(&self.shared)
+    }
+
+    /// 当前活着的接收者数量
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock().unwrap().receivers
+    }
+}
+
+fn new_receiver<T>(shared: &Arc<Shared<T>>) -> Receiver<T> {
+    let mut state = shared.state.lock().unwrap();
+    state.receivers += 1;
+    let id = state.next_rx_id;
+    state.next_rx_id += 1;
+    Receiver {
+        shared: shared.clone(),
+        seen_version: state.version,
+        id,
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.sender_dropped = true;
+        Shared::notify_receivers(&mut state);
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 查看当前值，不会把它标记为已看过
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            guard: self.shared.state.lock().unwrap(),
+        }
+    }
+
+    /// 查看当前值，并把它标记为已看过
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        let guard = self.shared.state.lock().unwrap();
+        self.seen_version = guard.version;
+        Ref { guard }
+    }
+
+    /// 自上次看过之后值是否变了
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        let state = self.shared.state.lock().unwrap();
+        if state.version != self.seen_version {
+            Ok(true)
+        } else if state.sender_dropped {
+            Err(RecvError)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// 等待值发生变化，完成后新值被标记为已看过
+    ///
+    /// 如果在上次看过之后已经变了，会立即完成
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed { rx: self }
+    }
+
+    /// 在手写的 Future 中等待值发生变化
+    pub fn poll_changed(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), RecvError>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.version != self.seen_version {
+            self.seen_version = state.version;
+            state.rx_wakers.remove(&self.id);
+            Poll::Ready(Ok(()))
+        } else if state.sender_dropped {
+            Poll::Ready(Err(RecvError))
+        } else {
+            state.rx_wakers.insert(self.id, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut rx = new_receiver(&self.shared);
+        rx.seen_version = self.seen_version;
+        rx
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers -= 1;
+        state.rx_wakers.remove(&self.id);
+    }
+}
+
+/// 作为 Stream 时，每次值发生变化就产出一份新值的 clone，Sender 被 drop 后结束
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        match this.poll_changed(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Some(this.borrow().clone())),
+            Poll::Ready(Err(RecvError)) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// `Receiver::changed` 返回的 Future
+pub struct Changed<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Changed<'_, T> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().rx.poll_changed(cx)
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watch channel has no receivers")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watch sender dropped")
+    }
+}
+
+impl Error for RecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn changed_resolves_after_send() {
+        let (tx, mut rx) = channel("init");
+        assert_eq!(rx.has_changed(), Ok(false));
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send("updated").unwrap();
+            tx
+        });
+        block_on(rx.changed()).unwrap();
+        assert_eq!(*rx.borrow(), "updated");
+        drop(handle.join().unwrap());
+        assert_eq!(block_on(rx.changed()), Err(RecvError));
+    }
+
+    #[test]
+    fn receivers_only_see_latest_value() {
+        let (tx, mut rx) = channel(0);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(*rx.borrow_and_update(), 2);
+        assert_eq!(rx.has_changed(), Ok(false));
+    }
+}
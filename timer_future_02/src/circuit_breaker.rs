@@ -0,0 +1,479 @@
+/*
+    熔断器：上游出问题时快速失败，而不是让每个请求都等到超时
+
+    let breaker = CircuitBreaker::new()
+        .failure_ratio(0.5)
+        .cooldown(Duration::from_secs(30));
+    let response = breaker.call(cheapo_request(&addrs, "example.com", "/")).await?;
+
+    - Closed：正常调用，记录最近 window 次调用的结果；
+      至少有 minimum_calls 次、并且失败的比例达到 failure_ratio 时变成 Open
+    - Open：不调用上游，直接返回 CircuitOpen 错误；cooldown 之后变成 HalfOpen
+    - HalfOpen：只放 probes 个试探请求过去，其他的仍然直接失败；
+      试探全部成功就回到 Closed，有一个失败就重新 Open，再等一个 cooldown
+
+    Open 到 HalfOpen 不需要后台任务，下一次 call 或 state 时发现 cooldown 已经过了就会转换。
+    状态的变化可以用 subscribe 拿到的 watch::Receiver 等待，也可以用 on_state_change 注册回调。
+    调用到一半被 drop 的 Future 不算成功也不算失败，试探的名额会还回去。
+    每次进入 HalfOpen 都有一个新的编号，上一轮试探的结果来得晚了会被忽略，不会算到这一轮里
+*/
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::channel::watch;
+
+/// 熔断器的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// 熔断器没有放行这次调用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen(());
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("circuit breaker is open")
+    }
+}
+
+impl Error for CircuitOpen {}
+
+// 不是 ConnectionRefused 之类的错误，这样按 kind 判断是否重试的代码不会马上再试一次
+impl From<CircuitOpen> for io::Error {
+    fn from(open: CircuitOpen) -> Self {
+        io::Error::other(open)
+    }
+}
+
+type Callback = Box<dyn Fn(CircuitState, CircuitState) + Send + Sync>;
+
+pub struct CircuitBreaker {
+    failure_ratio: f64,
+    minimum_calls: usize,
+    window: usize,
+    cooldown: Duration,
+    probes: usize,
+    state: Mutex<State>,
+    watch: watch::Sender<CircuitState>,
+    callback: Option<Callback>,
+    /// 进入过多少次 HalfOpen，只在 state 的锁里修改
+    half_opens: AtomicU64,
+}
+
+enum State {
+    /// 最近的调用结果，true 表示失败
+    Closed {
+        outcomes: VecDeque<bool>,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        /// 第几次进入 HalfOpen
+        period: u64,
+        started: usize,
+        succeeded: usize,
+    },
+}
+
+impl State {
+    fn kind(&self) -> CircuitState {
+        match self {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker::new()
+    }
+}
+
+impl CircuitBreaker {
+    /// 默认：最近 20 次中至少 10 次、失败一半以上时熔断，30 秒后放 1 个试探请求
+    pub fn new() -> Self {
+        CircuitBreaker {
+            failure_ratio: 0.5,
+            minimum_calls: 10,
+            window: 20,
+            cooldown: Duration::from_secs(30),
+            probes: 1,
+            state: Mutex::new(State::Closed {
+                outcomes: VecDeque::new(),
+            }),
+            watch: watch::channel(CircuitState::Closed).0,
+            callback: None,
+            half_opens: AtomicU64::new(0),
+        }
+    }
+
+    pub fn failure_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "failure ratio must be in (0, 1]"
+        );
+        self.failure_ratio = ratio;
+        self
+    }
+
+    /// 最近的调用少于这么多次时不熔断，免得刚启动时一两次失败就熔断
+    pub fn minimum_calls(mut self, calls: usize) -> Self {
+        assert!(calls > 0, "minimum_calls must be at least one");
+        self.minimum_calls = calls;
+        self
+    }
+
+    /// 计算失败比例时看最近多少次调用
+    pub fn window(mut self, calls: usize) -> Self {
+        assert!(calls > 0, "window must hold at least one call");
+        self.window = calls;
+        self
+    }
+
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// HalfOpen 时放过去的试探请求数，全部成功才回到 Closed
+    pub fn probes(mut self, probes: usize) -> Self {
+        assert!(probes > 0, "at least one probe is required");
+        self.probes = probes;
+        self
+    }
+
+    /// 状态变化时调用 `callback(旧状态, 新状态)`。
+    /// 为了按发生的顺序通知，回调在熔断器的锁里调用，回调中不能再使用这个熔断器
+    pub fn on_state_change(
+        mut self,
+        callback: impl Fn(CircuitState, CircuitState) + Send + Sync + 'static,
+    ) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut state = self.state.lock().unwrap();
+        let change = self.expire_cooldown(&mut state);
+        self.notify(change);
+        state.kind()
+    }
+
+    /// 可以用 `changed().await` 等待状态变化
+    pub fn subscribe(&self) -> watch::Receiver<CircuitState> {
+        self.watch.subscribe()
+    }
+
+    /// 熔断器允许时运行 `future`，根据结果更新状态；不允许时直接返回 CircuitOpen 转换成的错误
+    pub async fn call<F, T, E>(&self, future: F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: From<CircuitOpen>,
+    {
+        let permit = self.admit()?;
+        let result = future.await;
+        permit.finish(result.is_err());
+        result
+    }
+
+    // 判断这次调用能不能放过去，HalfOpen 时占一个试探名额
+    fn admit(&self) -> Result<Permit<'_>, CircuitOpen> {
+        let mut state = self.state.lock().unwrap();
+        let change = self.expire_cooldown(&mut state);
+        self.notify(change);
+        let admitted = match &mut *state {
+            State::Closed { .. } => Some(None),
+            State::Open { .. } => None,
+            State::HalfOpen {
+                period, started, ..
+            } if *started < self.probes => {
+                *started += 1;
+                Some(Some(*period))
+            }
+            State::HalfOpen { .. } => None,
+        };
+        match admitted {
+            Some(period) => Ok(Permit {
+                breaker: self,
+                period,
+                finished: false,
+            }),
+            None => Err(CircuitOpen(())),
+        }
+    }
+
+    // 记录一次调用的结果，`period` 是试探请求所在的 HalfOpen 的编号
+    fn record(&self, period: Option<u64>, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        let old = state.kind();
+        match &mut *state {
+            State::Closed { outcomes } => {
+                outcomes.push_back(failed);
+                if outcomes.len() > self.window {
+                    outcomes.pop_front();
+                }
+                let failures = outcomes.iter().filter(|&&failed| failed).count();
+                if outcomes.len() >= self.minimum_calls
+                    && failures as f64 >= self.failure_ratio * outcomes.len() as f64
+                {
+                    *state = self.open();
+                }
+            }
+            // 熔断之前就开始的调用，结果已经没有意义了
+            State::Open { .. } => {}
+            // 只算这一轮的试探请求
+            State::HalfOpen {
+                period: current,
+                succeeded,
+                ..
+            } if period == Some(*current) => {
+                if failed {
+                    *state = self.open();
+                } else {
+                    *succeeded += 1;
+                    if *succeeded >= self.probes {
+                        *state = State::Closed {
+                            outcomes: VecDeque::new(),
+                        };
+                    }
+                }
+            }
+            State::HalfOpen { .. } => {}
+        }
+        let new = state.kind();
+        self.notify((old != new).then_some((old, new)));
+    }
+
+    // 试探请求被取消了，还是这一轮的话把名额还回去
+    fn release_probe(&self, period: u64) {
+        if let State::HalfOpen {
+            period: current,
+            started,
+            ..
+        } = &mut *self.state.lock().unwrap()
+        {
+            if *current == period {
+                *started = started.saturating_sub(1);
+            }
+        }
+    }
+
+    fn open(&self) -> State {
+        State::Open {
+            until: Instant::now() + self.cooldown,
+        }
+    }
+
+    // cooldown 过了就从 Open 变成 HalfOpen，返回发生的变化
+    fn expire_cooldown(&self, state: &mut State) -> Option<(CircuitState, CircuitState)> {
+        match state {
+            State::Open { until } if Instant::now() >= *until => {
+                *state = State::HalfOpen {
+                    period: self.half_opens.fetch_add(1, Ordering::Relaxed) + 1,
+                    started: 0,
+                    succeeded: 0,
+                };
+                Some((CircuitState::Open, CircuitState::HalfOpen))
+            }
+            _ => None,
+        }
+    }
+
+    // 调用的时候还拿着 state 的锁，这样通知的顺序和状态变化的顺序一致
+    fn notify(&self, change: Option<(CircuitState, CircuitState)>) {
+        if let Some((old, new)) = change {
+            self.watch.send_replace(new);
+            if let Some(callback) = &self.callback {
+                callback(old, new);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("state", &self.state.lock().unwrap().kind())
+            .finish()
+    }
+}
+
+// 一次被放行的调用，没有 finish 就被 drop（调用被取消）时不记录结果
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    /// 试探请求所在的 HalfOpen 的编号，不是试探请求时是 None
+    period: Option<u64>,
+    finished: bool,
+}
+
+impl Permit<'_> {
+    fn finish(mut self, failed: bool) {
+        self.finished = true;
+        self.breaker.record(self.period, failed);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let (false, Some(period)) = (self.finished, self.period) {
+            self.breaker.release_probe(period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex};
+
+    fn call(breaker: &CircuitBreaker, ok: bool) -> io::Result<()> {
+        block_on(breaker.call(async move {
+            if ok {
+                Ok(())
+            } else {
+                Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            }
+        }))
+    }
+
+    fn is_open_error(result: io::Result<()>) -> bool {
+        result.is_err_and(|err| err.get_ref().is_some_and(|inner| inner.is::<CircuitOpen>()))
+    }
+
+    #[test]
+    fn opens_on_failure_ratio_and_recovers_after_probes() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let breaker = CircuitBreaker::new()
+            .minimum_calls(4)
+            .window(4)
+            .failure_ratio(0.5)
+            .cooldown(Duration::from_millis(30))
+            .probes(2)
+            .on_state_change({
+                let changes = changes.clone();
+                move |old, new| changes.lock().unwrap().push((old, new))
+            });
+
+        // 少于 minimum_calls 次时不熔断
+        call(&breaker, false).unwrap_err();
+        call(&breaker, true).unwrap();
+        call(&breaker, true).unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        call(&breaker, false).unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(is_open_error(call(&breaker, true)));
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        call(&breaker, true).unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        call(&breaker, true).unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        use CircuitState::*;
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [(Closed, Open), (Open, HalfOpen), (HalfOpen, Closed)]
+        );
+    }
+
+    #[test]
+    fn failed_probe_reopens_and_extra_calls_are_rejected() {
+        let breaker = CircuitBreaker::new()
+            .minimum_calls(1)
+            .cooldown(Duration::from_millis(20));
+        let mut states = breaker.subscribe();
+        call(&breaker, false).unwrap_err();
+        block_on(states.changed()).unwrap();
+        assert_eq!(*states.borrow_and_update(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(30));
+        // 试探请求还没有结束，其他的调用直接失败
+        let (tx, rx) = crate::channel::oneshot::channel::<bool>();
+        let probe = breaker.call(async move {
+            match rx.await {
+                Ok(true) => Ok(()),
+                _ => Err(io::Error::from(io::ErrorKind::ConnectionReset)),
+            }
+        });
+        let mut probe = Box::pin(probe);
+        assert!(poll_once(&mut probe).is_pending());
+        assert_eq!(*states.borrow_and_update(), CircuitState::HalfOpen);
+        assert!(is_open_error(call(&breaker, true)));
+
+        tx.send(false).unwrap();
+        block_on(probe).unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn probes_from_an_earlier_half_open_are_ignored() {
+        let breaker = CircuitBreaker::new()
+            .minimum_calls(1)
+            .cooldown(Duration::ZERO)
+            .probes(3);
+        call(&breaker, false).unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        // 第一轮的两个试探请求还没结束，第三个失败了，熔断器重新 Open 后马上进入第二轮
+        let (tx, rx) = crate::channel::oneshot::channel::<()>();
+        let mut late = Box::pin(breaker.call(async move {
+            rx.await.unwrap();
+            io::Result::Ok(())
+        }));
+        let mut cancelled = Box::pin(breaker.call(futures::future::pending::<io::Result<()>>()));
+        assert!(poll_once(&mut late).is_pending());
+        assert!(poll_once(&mut cancelled).is_pending());
+        call(&breaker, false).unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        // 上一轮的成功不算到这一轮，取消也不会还这一轮的名额
+        call(&breaker, true).unwrap();
+        tx.send(()).unwrap();
+        block_on(late).unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        let mut second = Box::pin(breaker.call(futures::future::pending::<io::Result<()>>()));
+        assert!(poll_once(&mut second).is_pending());
+        let mut third = Box::pin(breaker.call(futures::future::pending::<io::Result<()>>()));
+        assert!(poll_once(&mut third).is_pending());
+        drop(cancelled);
+        assert!(is_open_error(call(&breaker, true)));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn cancelled_probe_returns_its_slot() {
+        let breaker = CircuitBreaker::new()
+            .minimum_calls(1)
+            .cooldown(Duration::ZERO);
+        call(&breaker, false).unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let mut probe = Box::pin(breaker.call(futures::future::pending::<io::Result<()>>()));
+        assert!(poll_once(&mut probe).is_pending());
+        drop(probe);
+
+        call(&breaker, true).unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -7,6 +7,25 @@ use std::{
     time::Duration,
 };
 
+pub mod cache;
+pub mod channel;
+pub mod circuit_breaker;
+#[cfg(unix)]
+pub mod net;
+#[cfg(unix)]
+pub mod process;
+pub mod rate_limit;
+#[cfg(unix)]
+pub mod reactor;
+pub mod retry;
+pub mod shared;
+pub mod stream;
+pub mod sync;
+pub mod time;
+
+#[cfg(test)]
+mod test_util;
+
 /*
     TimerFuture 让线程来传达定时器的时间已经到了，这个 Future 可以完成了
 */
@@ -14,6 +14,7 @@ use std::{
 
 // lib.rs 中的 TimerFuture
 use timer_future_02::TimerFuture;
+use timer_future_02::sync::CancellationToken;
 
 
 /// 任务执行者，它会从 channel 收到任务并运行它们
@@ -147,13 +148,25 @@ impl Executor {
 fn main() {
     let (executor, spawner) = new_executor_and_spawner();
 
+    // 用来通知 worker 任务退出的令牌
+    let token = CancellationToken::new();
+    let worker_token = token.child_token();
+
     // Spawn a task to print before and after waiting on a timer.
     // 生成一个任务，让其等待一个 timer 前后进行打印
-    spawner.spawn(async {
+    spawner.spawn(async move {
         println!("[{:?}] howdy!", thread::current().id());
         // 等待 timer future 在 2s 后完成
         TimerFuture::new(Duration::new(2, 0)).await;
         println!("[{:?}] spawner async done!", thread::current().id());
+        // 取消父令牌，worker 持有的子令牌也随之被取消
+        token.cancel();
+    });
+
+    // worker 任务一直等到被取消才结束，否则执行者永远不会退出
+    spawner.spawn(async move {
+        worker_token.cancelled().await;
+        println!("[{:?}] worker cancelled, shutting down", thread::current().id());
     });
 
     // 丢弃生成器以便我们的执行者知道它已经完成了
@@ -0,0 +1,15 @@
+/*
+    注册到 Reactor 中的异步 socket
+
+    - UnixListener / UnixStream：本机进程之间通信（比如 sidecar 服务）
+    - UdpSocket：无连接的数据报，适合发送 metrics 这类丢了也没关系的数据
+
+    UnixStream 实现了 `futures::io::AsyncRead` / `AsyncWrite`，
+    所以可以像 web_server_09 中的 TcpStream 一样使用 `read`、`write_all` 等方法
+*/
+
+mod udp;
+mod unix;
+
+pub use udp::UdpSocket;
+pub use unix::{Incoming, UnixListener, UnixStream};
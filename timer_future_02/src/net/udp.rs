@@ -0,0 +1,111 @@
+use std::{
+    io,
+    net::{self, SocketAddr, ToSocketAddrs},
+    task::{Context, Poll},
+};
+
+use crate::reactor::Async;
+
+/// 异步的 UDP socket
+///
+/// 可以直接用 `send_to`/`recv_from` 和任意地址通信，
+/// 也可以先 `connect` 到一个地址，然后用 `send`/`recv`
+pub struct UdpSocket {
+    inner: Async<net::UdpSocket>,
+}
+
+impl UdpSocket {
+    /// 绑定到本地地址，端口传 0 表示由系统分配
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+        UdpSocket::from_std(net::UdpSocket::bind(addr)?)
+    }
+
+    /// 把标准库的 UdpSocket 注册到 Reactor 中
+    pub fn from_std(socket: net::UdpSocket) -> io::Result<UdpSocket> {
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket {
+            inner: Async::new(socket)?,
+        })
+    }
+
+    /// 设置默认的对端地址，之后 `send`/`recv` 都只和这个地址通信
+    ///
+    /// UDP 没有握手，connect 只是在内核中记录地址，不会等待
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        self.inner.get_ref().connect(addr)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+
+    /// 发送一个数据报到 `target`，返回发送的字节数
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> io::Result<usize> {
+        // 先解析地址，避免每次重试都解析一遍
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to send data to"))?;
+        self.inner.write_with(|socket| socket.send_to(buf, target)).await
+    }
+
+    /// 接收一个数据报，返回字节数和发送方的地址；`buf` 放不下的部分会被丢弃
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.read_with(|socket| socket.recv_from(buf)).await
+    }
+
+    /// 发送到 `connect` 设置的地址
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_with(|socket| socket.send(buf)).await
+    }
+
+    /// 从 `connect` 设置的地址接收
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_with(|socket| socket.recv(buf)).await
+    }
+
+    /// 在手写的 Future 中发送数据报
+    pub fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        self.inner.poll_write_with(cx, |socket| socket.send_to(buf, target))
+    }
+
+    /// 在手写的 Future 中接收数据报
+    pub fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<(usize, SocketAddr)>> {
+        self.inner.poll_read_with(cx, |socket| socket.recv_from(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+
+    #[test]
+    fn send_to_and_recv_from() {
+        block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let server_addr = server.local_addr().unwrap();
+
+            let handle = thread::spawn(move || {
+                block_on(async {
+                    let mut buf = [0u8; 64];
+                    let (n, from) = server.recv_from(&mut buf).await.unwrap();
+                    server.send_to(&buf[..n], from).await.unwrap();
+                })
+            });
+
+            let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.connect(server_addr).unwrap();
+            client.send(b"requests:1|c").await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = client.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"requests:1|c");
+            handle.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{
+            ffi::OsStrExt,
+            net::{self, SocketAddr},
+        },
+    },
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    io::{AsyncRead, AsyncWrite},
+    Stream,
+};
+
+use crate::reactor::Async;
+
+/// 异步的 Unix domain socket 监听者
+pub struct UnixListener {
+    inner: Async<net::UnixListener>,
+}
+
+/// 异步的 Unix domain socket 连接
+pub struct UnixStream {
+    inner: Async<net::UnixStream>,
+}
+
+impl UnixListener {
+    /// 在 `path` 上监听，`path` 已经存在时会失败
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixListener> {
+        let listener = net::UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(UnixListener {
+            inner: Async::new(listener)?,
+        })
+    }
+
+    /// 等待下一个连接
+    pub async fn accept(&self) -> io::Result<(UnixStream, SocketAddr)> {
+        let (stream, addr) = self.inner.read_with(|listener| listener.accept()).await?;
+        Ok((UnixStream::from_std(stream)?, addr))
+    }
+
+    /// 在手写的 Future 中等待下一个连接
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(UnixStream, SocketAddr)>> {
+        match self.inner.poll_read_with(cx, |listener| listener.accept()) {
+            Poll::Ready(Ok((stream, addr))) => Poll::Ready(UnixStream::from_std(stream).map(|s| (s, addr))),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// 把到来的连接当作 Stream，和 async-std 的 `TcpListener::incoming` 用法一样
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+}
+
+/// `UnixListener::incoming` 返回的 Stream，永远不会结束
+pub struct Incoming<'a> {
+    listener: &'a UnixListener,
+}
+
+impl Stream for Incoming<'_> {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    }
+}
+
+impl UnixStream {
+    /// 连接到 `path` 上的监听者
+    ///
+    /// 用非阻塞的 socket 发起 connect，还没有完成时等到 socket 可写，再检查连接的结果；
+    /// 监听者的 backlog 满了（EAGAIN）时等到 socket 可写再重新 connect
+    pub async fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixStream> {
+        let (addr, len) = sockaddr_un(path.as_ref())?;
+        let fd = socket()?;
+        let stream = UnixStream::from_std(net::UnixStream::from(fd))?;
+        let fd = stream.inner.get_ref().as_raw_fd();
+        let connect = || {
+            let ret = unsafe { libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len) };
+            if ret < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        };
+
+        match connect() {
+            Ok(()) => {}
+            Err(err) if err.raw_os_error() == Some(libc::EINPROGRESS) => {
+                // 连接完成（或失败）时 socket 变为可写，在那之前 peer_addr 返回 ENOTCONN
+                stream
+                    .inner
+                    .write_with(|stream| match stream.take_error()? {
+                        Some(err) => Err(err),
+                        None => match stream.peer_addr() {
+                            Err(err) if err.raw_os_error() == Some(libc::ENOTCONN) => {
+                                Err(io::ErrorKind::WouldBlock.into())
+                            }
+                            result => result.map(drop),
+                        },
+                    })
+                    .await?;
+            }
+            // EAGAIN 的 kind 就是 WouldBlock，write_with 会等到可写再试
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                stream.inner.write_with(|_| connect()).await?;
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(stream)
+    }
+
+    /// 创建一对互相连接的 socket
+    pub fn pair() -> io::Result<(UnixStream, UnixStream)> {
+        let (a, b) = net::UnixStream::pair()?;
+        Ok((UnixStream::from_std(a)?, UnixStream::from_std(b)?))
+    }
+
+    /// 把标准库的 UnixStream 注册到 Reactor 中
+    pub fn from_std(stream: net::UnixStream) -> io::Result<UnixStream> {
+        stream.set_nonblocking(true)?;
+        Ok(UnixStream {
+            inner: Async::new(stream)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+
+    /// 关闭读、写或两个方向
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.get_ref().shutdown(how)
+    }
+}
+
+impl AsyncRead for UnixStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_read_with(cx, |mut stream| stream.read(buf))
+    }
+}
+
+impl AsyncWrite for UnixStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_write_with(cx, |mut stream| stream.write(buf))
+    }
+
+    // socket 没有用户态缓冲区，不需要 flush
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.inner.get_ref().shutdown(Shutdown::Write))
+    }
+}
+
+// 创建非阻塞、带 FD_CLOEXEC 的 socket。Linux 上在 socket() 时就设置好，
+// 免得在设置 FD_CLOEXEC 之前 process 中 fork 出来的子进程继承这个 fd
+fn socket() -> io::Result<OwnedFd> {
+    #[cfg(target_os = "linux")]
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_UNIX,
+            libc::SOCK_STREAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+            0,
+        )
+    };
+    #[cfg(not(target_os = "linux"))]
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    #[cfg(not(target_os = "linux"))]
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+// 把路径转换成 connect 需要的地址，路径太长（包括结尾的 0）放不下时返回 InvalidInput
+fn sockaddr_un(path: &Path) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path must be shorter than SUN_LEN",
+        ));
+    }
+    for (dst, &src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = src as libc::c_char;
+    }
+    let len = std::mem::offset_of!(libc::sockaddr_un, sun_path) + bytes.len() + 1;
+    Ok((addr, len as libc::socklen_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use futures::StreamExt;
+    use std::thread;
+
+    #[test]
+    fn echo_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("timer_future_02-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            block_on(async {
+                let mut stream = listener.incoming().next().await.unwrap().unwrap();
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.unwrap();
+                stream.write_all(&buf).await.unwrap();
+            })
+        });
+
+        let reply = block_on(async {
+            let mut stream = UnixStream::connect(&path).await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            stream.close().await.unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).await.unwrap();
+            reply
+        });
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reply, "ping");
+
+        // 没有人在监听时 connect 直接返回错误
+        let err = block_on(UnixStream::connect(&path)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connect_waits_while_the_backlog_is_full() {
+        use crate::test_util::poll_once;
+
+        let path = std::env::temp_dir().join(format!("timer_future_02-{}-backlog.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // 一直连接、不 accept，直到 backlog 满了，connect 开始等待
+        let mut queued = Vec::new();
+        let mut pending = loop {
+            let mut connect = Box::pin(UnixStream::connect(&path));
+            match poll_once(&mut connect) {
+                Poll::Ready(stream) => queued.push(stream.unwrap()),
+                Poll::Pending => break connect,
+            }
+            assert!(queued.len() < 10_000, "backlog never filled up");
+        };
+
+        // accept 一个，backlog 空出位置后等待中的 connect 就能完成
+        block_on(listener.accept()).unwrap();
+        block_on(pending.as_mut()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,452 @@
+/*
+    异步的子进程
+
+    `std::process::Child::wait()` 会一直阻塞到子进程退出，放在执行者的线程上调用就会卡住所有任务。
+    这里的 `Child::wait()` 是一个 Future：
+    - Linux 上用 pidfd：子进程退出时 pidfd 变为可读，把它注册到 Reactor 中，和 socket 一样等待
+    - 没有 pidfd 时（老内核或其他 Unix），和 TimerFuture 一样开一个线程，
+      用 `waitid(WNOWAIT)` 等待子进程退出（不回收），然后通过 oneshot channel 唤醒等待的任务
+
+    子进程的 stdin/stdout/stderr 设置成管道时，会被设置为非阻塞并注册到 Reactor 中，
+    分别实现了 `AsyncWrite` / `AsyncRead`
+*/
+
+use std::{
+    ffi::OsStr,
+    io::{self, Read, Write},
+    os::unix::io::{AsRawFd, RawFd},
+    path::Path,
+    pin::Pin,
+    process::{self, ExitStatus, Output, Stdio},
+    task::{Context, Poll},
+    thread,
+};
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use crate::channel::oneshot;
+use crate::reactor::Async;
+
+/// 和 `std::process::Command` 一样的构建方式，`spawn` 出来的是异步的 `Child`
+pub struct Command {
+    inner: process::Command,
+
+    /// 调用过 `stdin`、`stdout`、`stderr` 设置过的流，`output` 只给没有设置过的流换上默认值
+    stdin_set: bool,
+    stdout_set: bool,
+    stderr_set: bool,
+}
+
+/// 正在运行的子进程
+pub struct Child {
+    child: process::Child,
+
+    /// 设置为 `Stdio::piped()` 时才有值
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+
+    reaper: Reaper,
+
+    /// 子进程退出后缓存退出状态，重复 wait 时直接返回
+    status: Option<ExitStatus>,
+}
+
+// 得知子进程退出的方式
+enum Reaper {
+    #[cfg(target_os = "linux")]
+    PidFd(Async<std::os::fd::OwnedFd>),
+    Thread(oneshot::Receiver<()>),
+}
+
+/// 子进程的标准输入，drop 时关闭，子进程会读到 EOF
+pub struct ChildStdin {
+    inner: Async<process::ChildStdin>,
+}
+
+/// 子进程的标准输出
+pub struct ChildStdout {
+    inner: Async<process::ChildStdout>,
+}
+
+/// 子进程的标准错误
+pub struct ChildStderr {
+    inner: Async<process::ChildStderr>,
+}
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        Command {
+            inner: process::Command::new(program),
+            stdin_set: false,
+            stdout_set: false,
+            stderr_set: false,
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Command {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.inner.env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdin(cfg);
+        self.stdin_set = true;
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdout(cfg);
+        self.stdout_set = true;
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stderr(cfg);
+        self.stderr_set = true;
+        self
+    }
+
+    /// 启动子进程
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let mut child = self.inner.spawn()?;
+        let stdin = child.stdin.take().map(ChildStdin::new).transpose()?;
+        let stdout = child.stdout.take().map(ChildStdout::new).transpose()?;
+        let stderr = child.stderr.take().map(ChildStderr::new).transpose()?;
+        let reaper = Reaper::new(child.id())?;
+        Ok(Child {
+            child,
+            stdin,
+            stdout,
+            stderr,
+            reaper,
+            status: None,
+        })
+    }
+
+    /// 启动子进程并等待它退出，stdin/stdout/stderr 默认继承当前进程的
+    pub async fn status(&mut self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait().await
+    }
+
+    /// 启动子进程，收集它全部的 stdout 和 stderr
+    ///
+    /// 和 `std::process::Command::output` 一样，没有设置 stdin 时使用 `Stdio::null()`，
+    /// 否则读取 stdin 的子进程会和当前进程抢终端的输入，或者一直等下去；
+    /// 没有设置 stdout、stderr 时使用 `Stdio::piped()`，设置过的保持不变
+    ///
+    /// 这些默认值只对这一次启动有效，之后再 `spawn`、`status` 时和调用 `output` 之前一样
+    pub async fn output(&mut self) -> io::Result<Output> {
+        self.set_unset_stdio(Stdio::null, Stdio::piped);
+        let child = self.spawn();
+        // 没有设置过的流默认就是继承，恢复成 inherit 和从来没有设置过的效果一样
+        self.set_unset_stdio(Stdio::inherit, Stdio::inherit);
+        child?.wait_with_output().await
+    }
+
+    // 只修改没有被用户设置过的流，不改变 *_set 标记
+    fn set_unset_stdio(&mut self, stdin: fn() -> Stdio, output: fn() -> Stdio) {
+        if !self.stdin_set {
+            self.inner.stdin(stdin());
+        }
+        if !self.stdout_set {
+            self.inner.stdout(output());
+        }
+        if !self.stderr_set {
+            self.inner.stderr(output());
+        }
+    }
+}
+
+impl Reaper {
+    fn new(pid: u32) -> io::Result<Reaper> {
+        #[cfg(target_os = "linux")]
+        if let Some(pidfd) = pidfd_open(pid) {
+            return Ok(Reaper::PidFd(Async::new(pidfd)?));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        thread::Builder::new()
+            .name(format!("timer_future_02-reaper-{pid}"))
+            .spawn(move || {
+                wait_without_reaping(pid);
+                let _ = tx.send(());
+            })?;
+        Ok(Reaper::Thread(rx))
+    }
+}
+
+// 内核不支持 pidfd 时返回 None
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: u32) -> Option<std::os::fd::OwnedFd> {
+    use std::os::fd::FromRawFd;
+
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, libc::PIDFD_NONBLOCK) };
+    if fd < 0 {
+        return None;
+    }
+    Some(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+// 阻塞到子进程退出，但不回收它，回收仍然交给 `std::process::Child::try_wait`
+fn wait_without_reaping(pid: u32) {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if ret == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return;
+        }
+    }
+}
+
+impl Child {
+    /// 子进程的 pid
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// 等待子进程退出
+    ///
+    /// 调用前会先关闭 stdin，避免子进程一直等待输入而导致两边互相等待
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+
+        match &mut self.reaper {
+            #[cfg(target_os = "linux")]
+            Reaper::PidFd(pidfd) => {
+                let child = &mut self.child;
+                // pidfd 可读就说明子进程退出了，这时 try_wait 一定能拿到状态
+                let status = pidfd
+                    .read_with(|_| match child.try_wait()? {
+                        Some(status) => Ok(status),
+                        None => Err(io::ErrorKind::WouldBlock.into()),
+                    })
+                    .await?;
+                self.status = Some(status);
+            }
+            Reaper::Thread(rx) => {
+                // 等待线程没能启动 waitid（rx 返回 Err）时，也照样去回收
+                let _ = rx.await;
+                self.status = Some(self.child.wait()?);
+            }
+        }
+        Ok(self.status.unwrap())
+    }
+
+    /// 不等待，子进程还在运行时返回 `Ok(None)`
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.status.is_none() {
+            self.status = self.child.try_wait()?;
+        }
+        Ok(self.status)
+    }
+
+    /// 发送 SIGKILL，不等待子进程退出
+    pub fn start_kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+        self.child.kill()
+    }
+
+    /// 发送 SIGKILL 并等待子进程退出（回收它，避免留下僵尸进程）
+    pub async fn kill(&mut self) -> io::Result<()> {
+        self.start_kill()?;
+        self.wait().await.map(|_| ())
+    }
+
+    /// 等待子进程退出，同时读完它的 stdout 和 stderr
+    ///
+    /// 必须同时读两个管道并等待退出，否则子进程写满管道后会阻塞，永远不会退出
+    pub async fn wait_with_output(mut self) -> io::Result<Output> {
+        drop(self.stdin.take());
+
+        async fn read_all<R: AsyncRead + Unpin>(pipe: Option<R>) -> io::Result<Vec<u8>> {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_end(&mut buf).await?;
+            }
+            Ok(buf)
+        }
+
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+        let (stdout, stderr) = futures::try_join!(read_all(stdout), read_all(stderr))?;
+        let status = self.wait().await?;
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+// 标准库的管道默认是阻塞的，注册到 Reactor 前要设置为非阻塞
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+impl ChildStdin {
+    fn new(stdin: process::ChildStdin) -> io::Result<Self> {
+        set_nonblocking(stdin.as_raw_fd())?;
+        Ok(ChildStdin {
+            inner: Async::new(stdin)?,
+        })
+    }
+}
+
+impl ChildStdout {
+    fn new(stdout: process::ChildStdout) -> io::Result<Self> {
+        set_nonblocking(stdout.as_raw_fd())?;
+        Ok(ChildStdout {
+            inner: Async::new(stdout)?,
+        })
+    }
+}
+
+impl ChildStderr {
+    fn new(stderr: process::ChildStderr) -> io::Result<Self> {
+        set_nonblocking(stderr.as_raw_fd())?;
+        Ok(ChildStderr {
+            inner: Async::new(stderr)?,
+        })
+    }
+}
+
+impl AsyncWrite for ChildStdin {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_write_with(cx, |mut stdin| stdin.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // 管道只有在 drop 时才真正关闭，要让子进程读到 EOF 请 drop 掉 ChildStdin
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for ChildStdout {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_read_with_mut(cx, |stdout| stdout.read(buf))
+    }
+}
+
+impl AsyncRead for ChildStderr {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_read_with_mut(cx, |stderr| stderr.read(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::AsyncWriteExt;
+
+    #[test]
+    fn pipes_stdin_to_stdout() {
+        let output = block_on(async {
+            let mut child = Command::new("cat")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.as_mut().unwrap().write_all(b"hello from stdin").await.unwrap();
+            child.wait_with_output().await.unwrap()
+        });
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello from stdin");
+    }
+
+    #[test]
+    fn output_does_not_inherit_stdin() {
+        // stdin 继承下来的话，cat 会一直等待当前进程的输入
+        let output = block_on(Command::new("cat").output()).unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn output_does_not_change_later_spawns() {
+        let mut command = Command::new("echo");
+        command.arg("hi");
+        let output = block_on(command.output()).unwrap();
+        assert_eq!(output.stdout, b"hi\n");
+
+        // output 之后 stdout 仍然是继承的，spawn 出来的 Child 没有管道
+        let mut child = command.spawn().unwrap();
+        assert!(child.stdin.is_none());
+        assert!(child.stdout.is_none());
+        assert!(child.stderr.is_none());
+        assert!(block_on(child.wait()).unwrap().success());
+
+        // 用户设置过的 stdout 不会被 output 换成管道
+        command.stdout(Stdio::null());
+        let output = block_on(command.output()).unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn kill_reaps_long_running_child() {
+        block_on(async {
+            let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+            assert!(child.try_wait().unwrap().is_none());
+            child.kill().await.unwrap();
+            assert!(!child.try_wait().unwrap().unwrap().success());
+        });
+    }
+}
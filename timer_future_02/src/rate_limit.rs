@@ -0,0 +1,363 @@
+/*
+    可以 `.await` 的限流器
+
+    let bucket = TokenBucket::new(10, Duration::from_secs(1)).burst(20);
+    bucket.acquire().await;          // 等到有许可
+    if bucket.try_acquire() { .. }   // 没有许可就直接返回 false，比如服务器回 429
+
+    - TokenBucket：桶里最多 burst 个令牌，每 per 这么长时间补充 rate 个；
+      平时攒下的令牌允许一次性突发使用，长期的速率不超过 rate / per
+    - SlidingWindow：任意长度为 window 的时间段内最多 limit 次，没有突发，但需要记录每一次的时间
+    - Keyed：每个 key（客户端 IP、目标主机）一个独立的限流器，用到时才创建
+
+    acquire 是预约式的：第一次 poll 时就在限流器里预约好时间（令牌可以被借成负数），
+    然后用 time::sleep_until 等到那个时间，所以等待者按先来后到拿到许可，不需要再排队唤醒。
+    等待中的 Future 被 drop 时把预约取消，许可还给后面的人
+*/
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::time::{sleep_until, Sleep};
+
+/// 限流器的共同接口，acquire 等方法都建立在预约之上
+pub trait RateLimiter {
+    /// 预约 `n` 个许可，返回可以使用它们的时间
+    fn reserve(&self, n: u32) -> Instant;
+
+    /// 现在就有 `n` 个许可时拿走它们
+    fn try_reserve(&self, n: u32) -> bool;
+
+    /// 取消一次还没有用上的预约
+    fn cancel(&self, n: u32, at: Instant);
+
+    /// 没有任何预约和使用的记录，和新创建的一样
+    fn is_idle(&self) -> bool;
+
+    fn acquire(&self) -> Acquire<'_, Self> {
+        self.acquire_many(1)
+    }
+
+    fn acquire_many(&self, n: u32) -> Acquire<'_, Self> {
+        Acquire {
+            limiter: self,
+            n,
+            reserved: None,
+            done: false,
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.try_reserve(1)
+    }
+}
+
+/// `RateLimiter::acquire` 返回的 Future
+pub struct Acquire<'a, L: RateLimiter + ?Sized> {
+    limiter: &'a L,
+    n: u32,
+    reserved: Option<Sleep>,
+    done: bool,
+}
+
+impl<L: RateLimiter + ?Sized> Future for Acquire<'_, L> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let sleep = this
+            .reserved
+            .get_or_insert_with(|| sleep_until(this.limiter.reserve(this.n)));
+        if Pin::new(sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        this.done = true;
+        Poll::Ready(())
+    }
+}
+
+impl<L: RateLimiter + ?Sized> Drop for Acquire<'_, L> {
+    fn drop(&mut self) {
+        if let (Some(sleep), false) = (&self.reserved, self.done) {
+            self.limiter.cancel(self.n, sleep.deadline());
+        }
+    }
+}
+
+/// 令牌桶
+pub struct TokenBucket {
+    burst: u32,
+    /// 每秒补充的令牌数
+    rate: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// 负数表示已经被预约出去、还没有补充上的令牌
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// 每 `per` 补充 `rate` 个令牌，默认最多攒 `rate` 个，一开始是满的
+    pub fn new(rate: u32, per: Duration) -> Self {
+        assert!(rate > 0, "rate must be at least one token");
+        assert!(!per.is_zero(), "refill period must not be zero");
+        TokenBucket {
+            burst: rate,
+            rate: rate as f64 / per.as_secs_f64(),
+            state: Mutex::new(Bucket {
+                tokens: rate as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// 最多攒多少个令牌，也就是最大的突发量
+    pub fn burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "burst must be at least one token");
+        self.burst = burst;
+        self.state.get_mut().unwrap().tokens = burst as f64;
+        self
+    }
+
+    /// 现在桶里的令牌数，有预约在等待时是负数
+    pub fn available(&self) -> f64 {
+        self.refill().tokens
+    }
+
+    // 按流逝的时间补充令牌，最多补到 burst
+    fn refill(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        let mut bucket = self.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst as f64);
+        bucket.last = now;
+        bucket
+    }
+}
+
+impl RateLimiter for TokenBucket {
+    fn reserve(&self, n: u32) -> Instant {
+        assert!(
+            n <= self.burst,
+            "cannot acquire more tokens than the burst size"
+        );
+        let mut bucket = self.refill();
+        bucket.tokens -= n as f64;
+        if bucket.tokens >= 0.0 {
+            bucket.last
+        } else {
+            bucket.last + Duration::from_secs_f64(-bucket.tokens / self.rate)
+        }
+    }
+
+    fn try_reserve(&self, n: u32) -> bool {
+        let mut bucket = self.refill();
+        if bucket.tokens < n as f64 {
+            return false;
+        }
+        bucket.tokens -= n as f64;
+        true
+    }
+
+    fn cancel(&self, n: u32, _at: Instant) {
+        let mut bucket = self.refill();
+        bucket.tokens = (bucket.tokens + n as f64).min(self.burst as f64);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.refill().tokens >= self.burst as f64
+    }
+}
+
+/// 滑动窗口
+pub struct SlidingWindow {
+    limit: u32,
+    window: Duration,
+    /// 窗口内每一次许可的时间，从早到晚排列，预约的许可的时间在将来
+    grants: Mutex<VecDeque<Instant>>,
+}
+
+impl SlidingWindow {
+    /// 任意 `window` 这么长的时间内最多 `limit` 次
+    pub fn new(limit: u32, window: Duration) -> Self {
+        assert!(limit > 0, "limit must be at least one");
+        SlidingWindow {
+            limit,
+            window,
+            grants: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // 去掉已经滑出窗口的记录
+    fn prune(&self) -> std::sync::MutexGuard<'_, VecDeque<Instant>> {
+        let mut grants = self.grants.lock().unwrap();
+        let now = Instant::now();
+        while grants.front().is_some_and(|&at| at + self.window <= now) {
+            grants.pop_front();
+        }
+        grants
+    }
+}
+
+impl RateLimiter for SlidingWindow {
+    fn reserve(&self, n: u32) -> Instant {
+        assert!(
+            n <= self.limit,
+            "cannot acquire more permits than the limit"
+        );
+        let mut grants = self.prune();
+        // 加上这 n 个之后超过 limit 时，要等到它们前面第 limit 个滑出窗口；
+        // n 个许可记成同一个时间，取消时才能找到它们
+        let len = grants.len() + n as usize;
+        let limit = self.limit as usize;
+        let now = Instant::now();
+        let at = if len > limit {
+            now.max(grants[len - 1 - limit] + self.window)
+        } else {
+            now
+        };
+        grants.extend((0..n).map(|_| at));
+        at
+    }
+
+    fn try_reserve(&self, n: u32) -> bool {
+        let mut grants = self.prune();
+        if grants.len() + n as usize > self.limit as usize {
+            return false;
+        }
+        let now = Instant::now();
+        grants.extend((0..n).map(|_| now));
+        true
+    }
+
+    fn cancel(&self, n: u32, at: Instant) {
+        let mut grants = self.grants.lock().unwrap();
+        for _ in 0..n {
+            match grants.iter().rposition(|&grant| grant == at) {
+                Some(index) => grants.remove(index),
+                None => break,
+            };
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.prune().is_empty()
+    }
+}
+
+/// 每个 key 一个独立的限流器
+pub struct Keyed<K, L> {
+    limiters: Mutex<HashMap<K, Arc<L>>>,
+    make: Box<dyn Fn() -> L + Send + Sync>,
+}
+
+impl<K: Hash + Eq, L: RateLimiter> Keyed<K, L> {
+    /// 第一次用到某个 key 时用 `make` 创建它的限流器
+    pub fn new(make: impl Fn() -> L + Send + Sync + 'static) -> Self {
+        Keyed {
+            limiters: Mutex::new(HashMap::new()),
+            make: Box::new(make),
+        }
+    }
+
+    pub fn get(&self, key: K) -> Arc<L> {
+        self.limiters
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new((self.make)()))
+            .clone()
+    }
+
+    /// 删掉空闲的限流器，key 很多（比如每个客户端 IP 一个）时需要定期调用，免得一直占着内存
+    pub fn prune(&self) {
+        self.limiters
+            .lock()
+            .unwrap()
+            .retain(|_, limiter| Arc::strong_count(limiter) > 1 || !limiter.is_idle());
+    }
+
+    pub fn len(&self) -> usize {
+        self.limiters.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+    use futures::{executor::block_on, future::join_all};
+
+    fn elapsed_millis(start: Instant) -> u128 {
+        start.elapsed().as_millis()
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_paces_at_the_rate() {
+        // 每 20ms 一个令牌，最多攒 3 个
+        let bucket = TokenBucket::new(1, Duration::from_millis(20)).burst(3);
+        let start = Instant::now();
+        let times: Vec<u128> = block_on(join_all((0..5).map(|_| {
+            let bucket = &bucket;
+            async move {
+                bucket.acquire().await;
+                elapsed_millis(start)
+            }
+        })));
+        assert!(times[..3].iter().all(|&t| t < 15), "{times:?}");
+        assert!(times[3] >= 20 && times[4] >= 40, "{times:?}");
+
+        assert!(!bucket.try_acquire());
+        assert!(bucket.available() < 1.0);
+    }
+
+    #[test]
+    fn sliding_window_limits_any_window_and_cancel_returns_the_slot() {
+        let window = SlidingWindow::new(2, Duration::from_millis(40));
+        assert!(window.try_acquire());
+        assert!(window.try_acquire());
+        assert!(!window.try_acquire());
+
+        // 预约了第 3 个之后放弃，第 4 个可以用它的位置
+        let mut cancelled = window.acquire();
+        assert!(poll_once(&mut cancelled).is_pending());
+        drop(cancelled);
+
+        let start = Instant::now();
+        block_on(window.acquire_many(2));
+        let waited = elapsed_millis(start);
+        assert!((30..80).contains(&waited), "{waited}");
+        assert!(!window.try_acquire());
+    }
+
+    #[test]
+    fn keyed_limiters_are_independent_and_pruned_when_idle() {
+        let limits = Keyed::new(|| TokenBucket::new(1, Duration::from_millis(20)));
+        assert!(limits.get("a").try_acquire());
+        assert!(!limits.get("a").try_acquire());
+        assert!(limits.get("b").try_acquire());
+        assert_eq!(limits.len(), 2);
+
+        std::thread::sleep(Duration::from_millis(30));
+        let held = limits.get("a");
+        limits.prune();
+        // 正在被使用的限流器不会被删掉
+        assert_eq!(limits.len(), 1);
+        drop(held);
+        limits.prune();
+        assert!(limits.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+/*
+    Reactor（反应器）
+
+    TimerFuture 为每个定时器生成一个线程，用 thread::sleep 等待时间到达后调用 waker。
+    IO 也可以这样做，但为每个 socket 开一个线程太浪费了。
+
+    Reactor 只用一个后台线程，借助操作系统的 epoll/kqueue（这里通过 `polling` crate）同时等待所有注册过的 fd：
+    1. IO 对象都设置成非阻塞的，读写返回 `WouldBlock` 时，把当前任务的 waker 交给 Reactor，然后返回 Pending
+    2. Reactor 线程发现 fd 可读/可写后，取出对应的 waker 并调用 wake()
+    3. 执行者再次 poll 这个 Future，这时读写就能成功了
+
+    因为只和 Waker 打交道，所以 Reactor 可以配合任何执行者使用。
+    本 crate 的 net、process 都建立在 `Async` 之上，其他 crate 也可以用它包装自己的 IO 对象
+    （比如 async_demo_01 中的 Socket），所有的 fd 共用同一个 Reactor 线程
+*/
+
+use std::{
+    collections::HashMap,
+    future::poll_fn,
+    io,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use polling::{Event, Events, Poller};
+
+pub(crate) struct Reactor {
+    poller: Poller,
+
+    /// 所有注册过的 fd，key 就是注册到 poller 中的 key
+    sources: Mutex<HashMap<usize, Arc<Source>>>,
+    next_key: AtomicUsize,
+}
+
+/// 注册到 Reactor 中的一个 fd
+pub(crate) struct Source {
+    fd: RawFd,
+    key: usize,
+    wakers: Mutex<Wakers>,
+}
+
+/// 等待可读、可写的任务，每个方向只记录最后一个等待者
+#[derive(Default)]
+struct Wakers {
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Reactor {
+    /// 全局唯一的 Reactor，第一次使用时启动后台线程
+    pub(crate) fn get() -> &'static Reactor {
+        static REACTOR: OnceLock<Reactor> = OnceLock::new();
+        REACTOR.get_or_init(|| {
+            thread::Builder::new()
+                .name("timer_future_02-reactor".into())
+                .spawn(|| Reactor::get().run())
+                .expect("failed to spawn reactor thread");
+            Reactor {
+                poller: Poller::new().expect("failed to create poller"),
+                sources: Mutex::new(HashMap::new()),
+                next_key: AtomicUsize::new(0),
+            }
+        })
+    }
+
+    /// 注册一个 fd，一开始不关心任何事件，等有任务在等它时再打开对应的兴趣
+    pub(crate) fn insert(&self, fd: RawFd) -> io::Result<Arc<Source>> {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let source = Arc::new(Source {
+            fd,
+            key,
+            wakers: Mutex::new(Wakers::default()),
+        });
+        // 调用者保证在 `remove` 之前 fd 一直有效
+        unsafe { self.poller.add(fd, Event::none(key))? };
+        self.sources.lock().unwrap().insert(key, source.clone());
+        Ok(source)
+    }
+
+    /// 注销 fd，必须在 fd 被关闭之前调用
+    pub(crate) fn remove(&self, source: &Source) -> io::Result<()> {
+        self.sources.lock().unwrap().remove(&source.key);
+        self.poller.delete(source.borrowed_fd())
+    }
+
+    // 按当前的等待者重新设置兴趣，poller 用的是 oneshot 模式，每次事件之后都要重新设置
+    fn rearm(&self, source: &Source, wakers: &Wakers) -> io::Result<()> {
+        let mut event = Event::none(source.key);
+        event.readable = wakers.reader.is_some();
+        event.writable = wakers.writer.is_some();
+        self.poller.modify(source.borrowed_fd(), event)
+    }
+
+    // Reactor 线程的主循环
+    fn run(&self) {
+        let mut events = Events::new();
+        loop {
+            events.clear();
+            match self.poller.wait(&mut events, None) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => panic!("reactor poll failed: {err}"),
+            }
+
+            let mut ready = Vec::new();
+            for event in events.iter() {
+                let Some(source) = self.sources.lock().unwrap().get(&event.key).cloned() else {
+                    continue;
+                };
+                let mut wakers = source.wakers.lock().unwrap();
+                if event.readable {
+                    ready.extend(wakers.reader.take());
+                }
+                if event.writable {
+                    ready.extend(wakers.writer.take());
+                }
+                // 另一个方向还有人在等的话要重新打开兴趣
+                if wakers.reader.is_some() || wakers.writer.is_some() {
+                    let _ = self.rearm(&source, &wakers);
+                }
+            }
+            // 在锁外面唤醒，避免 wake 中再次访问 Source 时死锁
+            for waker in ready {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Source {
+    fn borrowed_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+
+    /// 当前任务想在 fd 可读时被唤醒
+    pub(crate) fn register_readable(&self, waker: &Waker) -> io::Result<()> {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.reader = Some(waker.clone());
+        Reactor::get().rearm(self, &wakers)
+    }
+
+    /// 当前任务想在 fd 可写时被唤醒
+    pub(crate) fn register_writable(&self, waker: &Waker) -> io::Result<()> {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.writer = Some(waker.clone());
+        Reactor::get().rearm(self, &wakers)
+    }
+}
+
+/// 把一个非阻塞的 IO 对象注册到 Reactor 中
+///
+/// 读写都通过 `poll_read_with`/`poll_write_with` 进行：先直接尝试，
+/// 返回 `WouldBlock` 就登记 waker 然后返回 Pending。
+/// 重新设置兴趣时 epoll 会检查 fd 当前的状态，所以“尝试失败”和“登记 waker”之间到达的数据不会被错过
+pub struct Async<T: AsRawFd> {
+    io: Option<T>,
+    source: Arc<Source>,
+}
+
+impl<T: AsRawFd> Async<T> {
+    /// `io` 必须已经设置为非阻塞模式
+    ///
+    /// 只接受自己拥有 fd 的类型（能转换成 `OwnedFd`），fd 在 Async 被 drop、从 Reactor 中注销之前不会被关闭；
+    /// `RawFd`、`BorrowedFd` 这种借来的 fd 不能保证这一点
+    pub fn new(io: T) -> io::Result<Self>
+    where
+        T: AsFd + Into<OwnedFd>,
+    {
+        let source = Reactor::get().insert(io.as_fd().as_raw_fd())?;
+        Ok(Async {
+            io: Some(io),
+            source,
+        })
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.io.as_ref().unwrap()
+    }
+
+    /// fd 可读时唤醒 `waker`，只通知一次；给不通过 `poll_read_with` 读写的 Future 用
+    pub fn register_readable(&self, waker: &Waker) -> io::Result<()> {
+        self.source.register_readable(waker)
+    }
+
+    /// fd 可写时唤醒 `waker`，只通知一次
+    pub fn register_writable(&self, waker: &Waker) -> io::Result<()> {
+        self.source.register_writable(waker)
+    }
+
+    pub fn poll_read_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        match op(self.get_ref()) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.source.register_readable(cx.waker())?;
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    /// 和 `poll_read_with` 一样，给只对 `&mut T` 实现了 `Read` 的类型（比如子进程的管道）用
+    pub fn poll_read_with_mut<R>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&mut T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        match op(self.io.as_mut().unwrap()) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.source.register_readable(cx.waker())?;
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub fn poll_write_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        match op(self.get_ref()) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.source.register_writable(cx.waker())?;
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    /// 反复尝试 `op`，直到它不再返回 `WouldBlock`
+    pub async fn read_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        poll_fn(|cx| self.poll_read_with(cx, &mut op)).await
+    }
+
+    /// 反复尝试 `op`，直到它不再返回 `WouldBlock`
+    pub async fn write_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        poll_fn(|cx| self.poll_write_with(cx, &mut op)).await
+    }
+}
+
+impl<T: AsRawFd> Drop for Async<T> {
+    fn drop(&mut self) {
+        // 先从 Reactor 中注销，再关闭 fd
+        let _ = Reactor::get().remove(&self.source);
+        self.io.take();
+    }
+}
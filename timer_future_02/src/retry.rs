@@ -0,0 +1,298 @@
+/*
+    失败后按指数退避重试
+
+    let policy = RetryPolicy::new().max_attempts(5).jitter(Jitter::Full);
+    let response = retry(&policy, || cheapo_request(&addrs, "example.com", "/")).await?;
+
+    - 第 n 次失败后等待 initial_delay * multiplier^(n-1)，最多等待 max_delay
+    - jitter 让同时失败的大量客户端不要在同一时刻一起重试：
+      - None：就用上面算出来的时间
+      - Full：在 [0, 算出来的时间] 中随机取一个
+      - Decorrelated：在 [initial_delay, 上一次等待的时间 * 3] 中随机取一个，不超过 max_delay
+    - 达到 max_attempts 次（包括第一次）、或者再等下去就超过 max_elapsed 时，返回最后一次的错误
+    - retry_if 的 `is_retryable` 返回 false 的错误（比如 404、参数错误）直接返回，不再重试
+
+    等待用的是 time::sleep，不会阻塞线程，所以可以在任何执行者中使用
+*/
+
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use crate::time::sleep;
+
+/// 等待时间的随机化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    None,
+    Full,
+    Decorrelated,
+}
+
+/// 重试的策略
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: Jitter,
+    max_attempts: usize,
+    max_elapsed: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+impl RetryPolicy {
+    /// 默认：第一次等待 100ms，每次翻倍，最多等待 10s，Full jitter，最多尝试 5 次
+    pub fn new() -> Self {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: Jitter::Full,
+            max_attempts: 5,
+            max_elapsed: None,
+        }
+    }
+
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        assert!(multiplier >= 1.0, "multiplier must be at least 1");
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// 最多尝试几次，包括第一次，1 表示不重试
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        assert!(attempts > 0, "max_attempts must be at least one");
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// 从第一次尝试开始算，超过这个时间就不再重试
+    pub fn max_elapsed(mut self, elapsed: Duration) -> Self {
+        self.max_elapsed = Some(elapsed);
+        self
+    }
+
+    /// 依次产生每次失败之后的等待时间，最多 max_attempts - 1 个
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            retries: 0,
+            previous: self.initial_delay,
+        }
+    }
+}
+
+/// `RetryPolicy::backoff` 返回的迭代器
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    retries: usize,
+    previous: Duration,
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let policy = &self.policy;
+        if self.retries + 1 >= policy.max_attempts {
+            return None;
+        }
+        // 重试很多次之后乘出来的时间会超出 Duration 的范围，这时就用 max_delay
+        let factor = policy
+            .multiplier
+            .powi(self.retries.min(i32::MAX as usize) as i32);
+        let exponential = Duration::try_from_secs_f64(policy.initial_delay.as_secs_f64() * factor)
+            .map_or(policy.max_delay, |delay| delay.min(policy.max_delay));
+        self.retries += 1;
+
+        let delay = match policy.jitter {
+            Jitter::None => exponential,
+            Jitter::Full => random_between(Duration::ZERO, exponential),
+            Jitter::Decorrelated => {
+                let upper = self.previous.saturating_mul(3).max(policy.initial_delay);
+                random_between(policy.initial_delay, upper).min(policy.max_delay)
+            }
+        };
+        self.previous = delay;
+        Some(delay)
+    }
+}
+
+/// 运行 `op` 返回的 Future，失败时按照 `policy` 等待后重新调用 `op`
+pub async fn retry<F, Fut, T, E>(policy: &RetryPolicy, op: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_if(policy, op, |_: &E| true).await
+}
+
+/// 和 retry 一样，只是 `is_retryable` 返回 false 的错误直接返回
+pub async fn retry_if<F, Fut, T, E, P>(
+    policy: &RetryPolicy,
+    mut op: F,
+    mut is_retryable: P,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    P: FnMut(&E) -> bool,
+{
+    let start = Instant::now();
+    let mut backoff = policy.backoff();
+    loop {
+        let err = match op().await {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+        if !is_retryable(&err) {
+            return Err(err);
+        }
+        let Some(delay) = backoff.next() else {
+            return Err(err);
+        };
+        if let Some(max_elapsed) = policy.max_elapsed {
+            if start.elapsed() + delay > max_elapsed {
+                return Err(err);
+            }
+        }
+        sleep(delay).await;
+    }
+}
+
+// 在 [low, high] 中随机取一个时间，jitter 不需要密码学强度的随机数，xorshift 就够了
+fn random_between(low: Duration, high: Duration) -> Duration {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            // RandomState 每次都用不同的随机 key，用它来得到一个种子
+            RandomState::new().build_hasher().finish() | 1
+        });
+    }
+    if high <= low {
+        return low;
+    }
+    let random = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+    low + (high - low).mul_f64(fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::io;
+
+    #[test]
+    fn backoff_grows_exponentially_and_respects_limits() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(50))
+            .jitter(Jitter::None)
+            .max_attempts(6);
+        let delays: Vec<u64> = policy.backoff().map(|d| d.as_millis() as u64).collect();
+        assert_eq!(delays, [10, 20, 40, 50, 50]);
+
+        let full = policy.clone().jitter(Jitter::Full);
+        assert!(full
+            .backoff()
+            .zip(policy.backoff())
+            .all(|(jittered, max)| jittered <= max));
+
+        let decorrelated = policy.jitter(Jitter::Decorrelated).max_attempts(100);
+        assert!(decorrelated
+            .backoff()
+            .all(|d| d >= Duration::from_millis(10) && d <= Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn retries_until_success_or_attempts_run_out() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(5))
+            .max_attempts(4);
+
+        let mut calls = 0;
+        let result = block_on(retry(&policy, || {
+            calls += 1;
+            let attempt = calls;
+            async move {
+                if attempt < 3 {
+                    Err(attempt)
+                } else {
+                    Ok("done")
+                }
+            }
+        }));
+        assert_eq!((result, calls), (Ok("done"), 3));
+
+        let mut calls = 0;
+        let result: Result<(), _> = block_on(retry(&policy, || {
+            calls += 1;
+            async { Err("down") }
+        }));
+        assert_eq!((result, calls), (Err("down"), 4));
+    }
+
+    #[test]
+    fn stops_on_permanent_errors_and_max_elapsed() {
+        let policy = RetryPolicy::new().initial_delay(Duration::from_millis(5));
+        let mut calls = 0;
+        let result: Result<(), io::ErrorKind> = block_on(retry_if(
+            &policy,
+            || {
+                calls += 1;
+                async { Err(io::ErrorKind::NotFound) }
+            },
+            |err| *err != io::ErrorKind::NotFound,
+        ));
+        assert_eq!((result, calls), (Err(io::ErrorKind::NotFound), 1));
+
+        // 每次等待 40ms，总共只允许 100ms，所以最多尝试 3 次
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(40))
+            .multiplier(1.0)
+            .jitter(Jitter::None)
+            .max_attempts(100)
+            .max_elapsed(Duration::from_millis(100));
+        let start = Instant::now();
+        let mut calls = 0;
+        let result: Result<(), _> = block_on(retry(&policy, || {
+            calls += 1;
+            async { Err(()) }
+        }));
+        assert_eq!((result, calls), (Err(()), 3));
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,292 @@
+/*
+    可以 clone、被多个任务同时等待的 Future
+
+    普通的 Future 只能被一个任务 `.await`。Shared 把 Future 放进 Arc 里，
+    每个 clone 都可以被不同的任务等待，里面的 Future 只运行一次，结果 clone 给每一个等待者。
+
+    1. 每个等待者 poll 时先把自己的 waker 登记到 Notifier 中
+    2. 同一时间只有一个等待者真正地 poll 里面的 Future（状态变成 Polling），其他的直接返回 Pending
+    3. 里面的 Future 拿到的 waker 是 Notifier，它被唤醒时唤醒所有登记过的等待者
+    4. 正在 poll 的时候 Notifier 被唤醒了，poll 的一方会再 poll 一次，
+       因为这个唤醒叫醒的其他等待者看到的是 Polling，什么也不会做
+    5. 完成后结果保存下来，之后的每次 poll 都直接 clone 一份
+*/
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+/// 结果会被 clone 给每个等待者的 Future
+pub struct Shared<F: Future> {
+    inner: Arc<Inner<F>>,
+
+    /// 这个 clone 在 Notifier 中登记 waker 用的 key
+    key: usize,
+}
+
+struct Inner<F: Future> {
+    state: Mutex<State<F>>,
+    notifier: Arc<Notifier>,
+    next_key: AtomicUsize,
+}
+
+enum State<F: Future> {
+    Idle(Pin<Box<F>>),
+    Polling,
+    Done(F::Output),
+    /// poll 里面的 Future 时 panic 了
+    Poisoned,
+}
+
+struct Notifier {
+    /// poll 的过程中是否被唤醒过
+    woken: AtomicBool,
+    wakers: Mutex<HashMap<usize, Waker>>,
+}
+
+impl Wake for Notifier {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        wakers.into_values().for_each(Waker::wake);
+    }
+}
+
+impl<F: Future> Shared<F> {
+    pub fn new(future: F) -> Self {
+        Shared {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State::Idle(Box::pin(future))),
+                notifier: Arc::new(Notifier {
+                    woken: AtomicBool::new(false),
+                    wakers: Mutex::new(HashMap::new()),
+                }),
+                next_key: AtomicUsize::new(1),
+            }),
+            key: 0,
+        }
+    }
+
+    /// 已经完成时返回结果的一份 clone
+    pub fn peek(&self) -> Option<F::Output>
+    where
+        F::Output: Clone,
+    {
+        match &*self.inner.state.lock().unwrap() {
+            State::Done(output) => Some(output.clone()),
+            _ => None,
+        }
+    }
+
+    /// 有多少个 Shared 共享同一个 Future
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
+
+impl<F: Future> Clone for Shared<F> {
+    fn clone(&self) -> Self {
+        Shared {
+            inner: self.inner.clone(),
+            key: self.inner.next_key.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl<F: Future> Drop for Shared<F> {
+    fn drop(&mut self) {
+        self.inner.notifier.wakers.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl<F: Future> fmt::Debug for Shared<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared").field("key", &self.key).finish()
+    }
+}
+
+// poll 里面的 Future 时 panic 的话，把状态设成 Poisoned 并唤醒所有的等待者，
+// 它们再 poll 时会看到 Poisoned 并 panic，而不是一直等下去
+struct PollGuard<'a, F: Future> {
+    state: &'a Mutex<State<F>>,
+    notifier: &'a Notifier,
+}
+
+impl<F: Future> Drop for PollGuard<'_, F> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            if let Ok(mut state) = self.state.lock() {
+                *state = State::Poisoned;
+            }
+            if let Ok(mut wakers) = self.notifier.wakers.lock() {
+                std::mem::take(&mut *wakers).into_values().for_each(Waker::wake);
+            }
+        }
+    }
+}
+
+impl<F> Future for Shared<F>
+where
+    F: Future,
+    F::Output: Clone,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let inner = &self.inner;
+        inner
+            .notifier
+            .wakers
+            .lock()
+            .unwrap()
+            .insert(self.key, cx.waker().clone());
+
+        let mut future = {
+            let mut state = inner.state.lock().unwrap();
+            match std::mem::replace(&mut *state, State::Polling) {
+                State::Idle(future) => future,
+                State::Polling => return Poll::Pending,
+                State::Done(output) => {
+                    *state = State::Done(output.clone());
+                    drop(state);
+                    inner.notifier.wakers.lock().unwrap().remove(&self.key);
+                    return Poll::Ready(output);
+                }
+                State::Poisoned => {
+                    *state = State::Poisoned;
+                    panic!("inner future of Shared panicked during poll");
+                }
+            }
+        };
+
+        let guard = PollGuard {
+            state: &inner.state,
+            notifier: &inner.notifier,
+        };
+        let waker = Waker::from(inner.notifier.clone());
+        let mut inner_cx = Context::from_waker(&waker);
+        loop {
+            inner.notifier.woken.store(false, Ordering::SeqCst);
+            if let Poll::Ready(output) = future.as_mut().poll(&mut inner_cx) {
+                *inner.state.lock().unwrap() = State::Done(output.clone());
+                std::mem::forget(guard);
+                // 叫醒其他的等待者来取结果
+                waker.wake_by_ref();
+                inner.notifier.wakers.lock().unwrap().remove(&self.key);
+                return Poll::Ready(output);
+            }
+            // 在锁里检查 woken 并放回 Future，这之后的唤醒叫醒的等待者一定能看到 Idle
+            let mut state = inner.state.lock().unwrap();
+            if !inner.notifier.woken.swap(false, Ordering::SeqCst) {
+                *state = State::Idle(future);
+                std::mem::forget(guard);
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::oneshot;
+    use futures::{executor::block_on, future::join_all};
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    #[test]
+    fn output_is_computed_once_and_cloned_to_every_awaiter() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = oneshot::channel::<String>();
+        let shared = Shared::new({
+            let runs = runs.clone();
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                rx.await.unwrap()
+            }
+        });
+
+        // 在不同的线程中等待同一个结果
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || block_on(shared))
+            })
+            .collect();
+        thread::sleep(std::time::Duration::from_millis(20));
+        tx.send("hello".to_string()).unwrap();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "hello");
+        }
+        assert_eq!(shared.peek().as_deref(), Some("hello"));
+        assert_eq!(block_on(join_all([shared.clone(), shared])).len(), 2);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn panic_in_inner_future_poisons_other_awaiters() {
+        let shared = Shared::new(async {
+            panic!("boom");
+        });
+        let other = shared.clone();
+        assert!(thread::spawn(move || block_on(shared)).join().is_err());
+        let err = thread::spawn(move || block_on(other)).join().unwrap_err();
+        assert!(err
+            .downcast_ref::<&str>()
+            .unwrap()
+            .contains("panicked during poll"));
+    }
+
+    #[test]
+    fn panic_wakes_awaiters_that_are_already_pending() {
+        struct Flag(AtomicBool);
+
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let (polling_tx, polling_rx) = std::sync::mpsc::channel();
+        let (panic_tx, panic_rx) = std::sync::mpsc::channel::<()>();
+        let shared = Shared::new(async move {
+            rx.await.unwrap();
+            // 让另一个等待者在这次 poll 的过程中登记 waker，然后再 panic
+            polling_tx.send(()).unwrap();
+            panic_rx.recv().unwrap();
+            panic!("boom");
+        });
+        let mut other = shared.clone();
+        let poller = thread::spawn(move || block_on(shared));
+        thread::sleep(std::time::Duration::from_millis(20));
+        tx.send(()).unwrap();
+        polling_rx.recv().unwrap();
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut other).poll(&mut cx).is_pending());
+
+        panic_tx.send(()).unwrap();
+        assert!(poller.join().is_err());
+        assert!(flag.0.load(Ordering::SeqCst));
+        let err = thread::spawn(move || block_on(other)).join().unwrap_err();
+        assert!(err
+            .downcast_ref::<&str>()
+            .unwrap()
+            .contains("panicked during poll"));
+    }
+}
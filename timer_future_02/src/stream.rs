@@ -0,0 +1,386 @@
+/*
+    stream! / try_stream!：用 async 块的写法来写 Stream
+
+    let numbers = stream! {
+        for i in 0..3 {
+            sleep(Duration::from_millis(10)).await;
+            yield i;
+        }
+    };
+
+    手写 Stream 要把所有的局部变量都变成结构体的字段，自己维护状态机；
+    async 块的状态机由编译器生成，只是它没有 yield。这两个宏把它们结合起来：
+    1. 宏把块中的每个 `yield x;` 改写成 `yielder.send(x).await;`
+    2. send 返回的 Future 把 x 放进和 AsyncStream 共享的 slot，第一次 poll 时返回 Pending
+    3. AsyncStream::poll_next poll 这个 async 块，poll 返回后 slot 中有值就把它作为下一个元素返回；
+       下次 poll_next 时 async 块从 `.await` 处继续，send 的 Future 这次返回 Ready
+    4. async 块结束时 Stream 也结束了
+
+    yield 之间可以借用 async 块自己的局部变量（比如 `for x in &list { yield *x; }`），
+    这样 async 块就是一个自引用的结构（pin_04 中的 Test），不能再被移动。
+    所以 AsyncStream 对 async 块做了结构化的 pin：只有 AsyncStream 自己被 pin 住了才能 poll_next，
+    而 poll_next 中 async 块从来不会被移出来
+
+    try_stream! 的元素是 Result<T, E>：`yield x` 产生 Ok(x)，块中可以使用 `?`，
+    出错时产生一个 Err(e) 然后结束。E 需要从使用的地方推断出来，比如函数的返回类型。
+
+    限制：yield 只能作为单独的语句出现在 stream! 的块以及 if、for、match 等语句的块里。
+    let 的初始化表达式里的块、后面跟着分号的 `if .. { .. };` 会被整个照抄，里面的 yield 不会被改写
+*/
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+/// 用 async 块实现的 Stream，由 stream!、try_stream! 创建
+pub struct AsyncStream<T, Fut> {
+    slot: Arc<Mutex<Option<T>>>,
+    generator: Fut,
+    done: bool,
+}
+
+impl<T, Fut: Future> AsyncStream<T, Fut> {
+    /// `body` 拿到 Yielder 后返回 async 块，一般不直接调用，而是使用 stream! 宏
+    pub fn new<F>(body: F) -> Self
+    where
+        F: FnOnce(Yielder<T>) -> Fut,
+    {
+        let slot = Arc::new(Mutex::new(None));
+        let generator = body(Yielder { slot: slot.clone() });
+        AsyncStream {
+            slot,
+            generator,
+            done: false,
+        }
+    }
+}
+
+/// async 块结束时的结果怎样变成 Stream 的最后一个元素
+pub trait StreamEnd<T> {
+    fn finish(self) -> Option<T>;
+}
+
+impl<T> StreamEnd<T> for () {
+    fn finish(self) -> Option<T> {
+        None
+    }
+}
+
+impl<T, E> StreamEnd<Result<T, E>> for Result<(), E> {
+    fn finish(self) -> Option<Result<T, E>> {
+        self.err().map(Err)
+    }
+}
+
+impl<T, Fut> Stream for AsyncStream<T, Fut>
+where
+    Fut: Future,
+    Fut::Output: StreamEnd<T>,
+{
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // 和 pin_04 中一样，!Unpin 的类型只能用 unsafe 拿到 &mut，
+        // 这里只是用它来访问字段，generator 被重新 pin 住，永远不会被移动
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let generator = unsafe { Pin::new_unchecked(&mut this.generator) };
+        let result = generator.poll(cx);
+
+        if let Some(item) = this.slot.lock().unwrap().take() {
+            return Poll::Ready(Some(item));
+        }
+        match result {
+            Poll::Ready(output) => {
+                this.done = true;
+                Poll::Ready(output.finish())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T, Fut> FusedStream for AsyncStream<T, Fut>
+where
+    Fut: Future,
+    Fut::Output: StreamEnd<T>,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+/// 把值交给 AsyncStream，`yield x;` 会被改写成 `yielder.send(x).await;`
+pub struct Yielder<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> Yielder<T> {
+    pub fn send(&mut self, value: T) -> Yield<'_, T> {
+        Yield {
+            slot: &self.slot,
+            value: Some(value),
+        }
+    }
+}
+
+/// `Yielder::send` 返回的 Future
+pub struct Yield<'a, T> {
+    slot: &'a Mutex<Option<T>>,
+    value: Option<T>,
+}
+
+// 没有对 value 做结构化的 pin
+impl<T> Unpin for Yield<'_, T> {}
+
+impl<T> Future for Yield<'_, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        match self.value.take() {
+            // 不需要唤醒：poll_next 看到 slot 中有值会立即返回，下一次 poll_next 会接着 poll
+            Some(value) => {
+                let previous = self.slot.lock().unwrap().replace(value);
+                assert!(previous.is_none(), "yield used outside of its stream");
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+/// 用 async 块的写法写一个 Stream，`yield x;` 产生一个元素
+#[macro_export]
+macro_rules! stream {
+    ($($body:tt)*) => {
+        $crate::stream::AsyncStream::new(|#[allow(unused_mut)] mut __yielder| async move {
+            $crate::__stream_body!(__yielder plain stmt [] [] $($body)*)
+        })
+    };
+}
+
+/// 和 stream! 一样，只是元素是 Result，`yield x;` 产生 Ok(x)，块中可以使用 `?`
+#[macro_export]
+macro_rules! try_stream {
+    ($($body:tt)*) => {
+        $crate::stream::AsyncStream::new(|#[allow(unused_mut)] mut __yielder| async move {
+            $crate::__stream_body!(__yielder ok stmt [] [] $($body)*);
+            #[allow(unreachable_code)]
+            ::core::result::Result::Ok(())
+        })
+    };
+}
+
+// 把块中的 `yield x;` 改写成 `yielder.send(x).await;`，第二个参数是模式（plain / ok），
+// 第三个参数表示当前是不是在语句的开头（stmt / mid），`[$($out)*]` 是已经改写好的 token。
+// 宏每展开一次都算一层递归，为了不超过递归深度的限制，尽量一次处理一大段：
+// - 语句开头的 `let ..;` 和 `表达式;` 直接当作一整个语句照抄。yield 只会出现在 if、for、match 等语句的块里，
+//   这些语句后面没有分号，所以不会被整个抄走，而是逐个 token 地处理，遇到块再进去改写
+// - () 和 [] 里面不会有 yield 语句，整个照抄
+// - 遇到 {} 时把当前的输出和剩下的 token 压到栈上，先改写块里面的，改写完后再包上 {} 放回外层的输出
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __stream_body {
+    // 全部改写完了，包成一个块，这样在表达式和语句的位置都能用
+    ($y:ident $mode:ident $at:ident [$($out:tt)*] []) => {
+        { $($out)* }
+    };
+    // 块里面改写完了，回到外层。块后面一般是新的语句；也可能是 `.f()`、`else` 这种不能作为表达式开头的，
+    // 照抄整个语句的规则不会匹配它们
+    ($y:ident $mode:ident $at:ident [$($out:tt)*] [[[$($up:tt)*] [$($rest:tt)*]] $($stack:tt)*]) => {
+        $crate::__stream_body!($y $mode stmt [$($up)* { $($out)* }] [$($stack)*] $($rest)*)
+    };
+    ($y:ident plain $at:ident [$($out:tt)*] [$($stack:tt)*] yield $e:expr ; $($rest:tt)*) => {
+        $crate::__stream_body!($y plain stmt [$($out)* $y.send($e).await;] [$($stack)*] $($rest)*)
+    };
+    ($y:ident ok $at:ident [$($out:tt)*] [$($stack:tt)*] yield $e:expr ; $($rest:tt)*) => {
+        $crate::__stream_body!(
+            $y ok stmt [$($out)* $y.send(::core::result::Result::Ok($e)).await;] [$($stack)*] $($rest)*
+        )
+    };
+    // 进入块，块里面从语句的开头开始
+    ($y:ident $mode:ident $at:ident [$($out:tt)*] [$($stack:tt)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode stmt [] [[[$($out)*] [$($rest)*]] $($stack)*] $($inner)*)
+    };
+    // let 语句重新输出时自己就带着分号，单独处理
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] let $($rest:tt)*) => {
+        $crate::__stream_body!(@let $y $mode [$($out)*] [$($stack)*] let $($rest)*)
+    };
+    // 这些开头的可能是条目（`const X: T = ..;`、`unsafe fn`）或者带属性的语句，
+    // 不能当作表达式来解析，逐个 token 处理
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] const $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* const] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] static $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* static] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] unsafe $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* unsafe] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] async $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* async] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] use $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* use] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] # $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* #] [$($stack)*] $($rest)*)
+    };
+    // 以分号结尾的表达式语句，整个照抄
+    ($y:ident $mode:ident stmt [$($out:tt)*] [$($stack:tt)*] $e:expr ; $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode stmt [$($out)* $e;] [$($stack)*] $($rest)*)
+    };
+    ($y:ident $mode:ident $at:ident [$($out:tt)*] [$($stack:tt)*] ; $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode stmt [$($out)* ;] [$($stack)*] $($rest)*)
+    };
+    // 其他的 token 原样输出，() 和 [] 也是一个 token
+    ($y:ident $mode:ident $at:ident [$($out:tt)*] [$($stack:tt)*] $token:tt $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* $token] [$($stack)*] $($rest)*)
+    };
+    (@let $y:ident $mode:ident [$($out:tt)*] [$($stack:tt)*] $s:stmt ; $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode stmt [$($out)* $s] [$($stack)*] $($rest)*)
+    };
+    (@let $y:ident $mode:ident [$($out:tt)*] [$($stack:tt)*] let $($rest:tt)*) => {
+        $crate::__stream_body!($y $mode mid [$($out)* let] [$($stack)*] $($rest)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::time::sleep;
+    use futures::{executor::block_on, Stream, StreamExt};
+    use std::{io, time::Duration};
+
+    #[test]
+    fn yields_from_loops_branches_and_awaits() {
+        let evens = stream! {
+            for i in 0..10 {
+                if i % 2 == 0 {
+                    sleep(Duration::from_millis(1)).await;
+                    yield i * 10;
+                }
+            }
+            yield 100;
+        };
+        let items: Vec<i32> = block_on(evens.collect());
+        assert_eq!(items, [0, 20, 40, 60, 80, 100]);
+    }
+
+    #[test]
+    fn can_borrow_locals_and_captures_across_yields() {
+        fn words(text: &str) -> impl Stream<Item = &str> + '_ {
+            stream! {
+                // 局部变量 list 和指向它的引用都保存在被 pin 住的 async 块里
+                let list: Vec<&str> = text.split_whitespace().collect();
+                for word in &list {
+                    yield *word;
+                }
+            }
+        }
+        let text = String::from("pin all the things");
+        let stream = words(&text);
+        // 和 pin_04 一样，自引用的 Stream 要先 pin 住才能使用
+        futures::pin_mut!(stream);
+        assert_eq!(block_on(stream.next()), Some("pin"));
+        assert_eq!(block_on(stream.collect::<Vec<_>>()), ["all", "the", "things"]);
+    }
+
+    #[test]
+    fn long_body_stays_within_the_recursion_limit() {
+        #[derive(Debug, PartialEq)]
+        enum Token {
+            Number(u32),
+            Word(String),
+            Symbol(char),
+        }
+
+        fn tokenize(source: &'static str) -> impl Stream<Item = io::Result<Token>> {
+            try_stream! {
+                let mut chars = source.chars().peekable();
+                while let Some(&c) = chars.peek() {
+                    match c {
+                        ' ' | '\t' => {
+                            chars.next();
+                        }
+                        '0'..='9' => {
+                            let mut value = 0u32;
+                            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                                value = value * 10 + digit;
+                                chars.next();
+                            }
+                            yield Token::Number(value);
+                        }
+                        'a'..='z' | 'A'..='Z' => {
+                            let mut word = String::new();
+                            while let Some(&c) = chars.peek() {
+                                if !c.is_alphanumeric() {
+                                    break;
+                                }
+                                word.push(c);
+                                chars.next();
+                            }
+                            sleep(Duration::from_millis(1)).await;
+                            yield Token::Word(word);
+                        }
+                        '+' | '-' | '*' | '/' | '(' | ')' => {
+                            chars.next();
+                            yield Token::Symbol(c);
+                        }
+                        other => {
+                            let message = format!("unexpected character {:?}", other);
+                            Err(io::Error::new(io::ErrorKind::InvalidData, message))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let tokens: Vec<_> = block_on(tokenize("x1 + (42 * y)").collect());
+        let tokens: Vec<_> = tokens.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Word("x1".into()),
+                Token::Symbol('+'),
+                Token::Symbol('('),
+                Token::Number(42),
+                Token::Symbol('*'),
+                Token::Word("y".into()),
+                Token::Symbol(')'),
+            ]
+        );
+
+        let tokens: Vec<_> = block_on(tokenize("1 ? 2").collect());
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].as_ref().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn try_stream_yields_ok_then_the_error() {
+        fn parse_all(input: &'static [&'static str]) -> impl Stream<Item = io::Result<u32>> {
+            try_stream! {
+                for text in input {
+                    let n = text
+                        .parse::<u32>()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    yield n;
+                }
+            }
+        }
+        let items: Vec<_> = block_on(parse_all(&["1", "2", "x", "4"]).collect());
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap(), &1);
+        assert_eq!(items[1].as_ref().unwrap(), &2);
+        assert_eq!(items[2].as_ref().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let ok: Vec<_> = block_on(parse_all(&["7"]).collect());
+        assert!(matches!(ok[..], [Ok(7)]));
+    }
+}
@@ -0,0 +1,38 @@
+/*
+    异步的同步原语
+
+    `TimerFuture` 的共享状态和执行者 `Task::future` 用的都是 `std::sync::Mutex`：
+    拿不到锁时整个工作线程都会阻塞，执行者在这个线程上的其他任务也就没法取得进展。
+    它们只在 poll 里短暂地加锁，所以问题不大；但如果要在持有锁的期间 `.await`，就必须用异步的锁。
+
+    这里的锁拿不到时不会阻塞线程，而是把当前任务的 waker 放进一个先进先出的等待队列，然后返回 Pending，
+    锁被释放时按排队顺序唤醒等待者。
+
+    - Semaphore：公平的信号量，其余的锁都建立在它之上
+    - Mutex：只有 1 个许可的信号量 + 被保护的数据
+    - RwLock：读锁拿 1 个许可，写锁拿全部许可
+    - Notify：不带数据的通知，Barrier：凑齐 n 个任务再一起继续
+    - CancellationToken：可以分层的取消令牌，用来让 spawn 出去的任务协作式地退出
+    - OnceCell：异步初始化一次的值，同时初始化的任务只有一个真正运行初始化
+
+    获取锁的 Future 在完成前被 drop（比如被 select 取消），会把自己从队列中移除；
+    如果许可已经分配给了它，会把许可还回去，所以获取操作是 cancel-safe 的。
+*/
+
+mod barrier;
+mod cancellation;
+mod mutex;
+mod notify;
+mod once_cell;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use cancellation::{CancellationToken, DropGuard};
+pub use mutex::{Mutex, MutexGuard, OwnedMutexGuard};
+pub use notify::{Notified, Notify};
+pub use once_cell::OnceCell;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{
+    Acquire, AcquireError, OwnedSemaphorePermit, Semaphore, SemaphorePermit, TryAcquireError,
+};
@@ -0,0 +1,69 @@
+use std::sync::Mutex;
+
+use crate::channel::watch;
+
+/// 异步屏障：`n` 个任务都调用了 `wait().await` 之后，它们才会一起继续往下走
+///
+/// 可以重复使用，每凑齐 `n` 个任务就算一代（generation）
+pub struct Barrier {
+    n: usize,
+    state: Mutex<State>,
+
+    /// 每一代结束时发送新的代数，等待中的任务通过 watch channel 得知这一代已经凑齐了
+    generation_tx: watch::Sender<u64>,
+}
+
+struct State {
+    arrived: usize,
+    generation: u64,
+}
+
+/// `Barrier::wait` 的结果，每一代中恰好有一个任务是 leader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// 是否是这一代中最后到达的那个任务
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// `n` 为 0 时和 1 一样，`wait` 会立即完成
+    pub fn new(n: usize) -> Self {
+        let (generation_tx, _) = watch::channel(0);
+        Barrier {
+            n: n.max(1),
+            state: Mutex::new(State {
+                arrived: 0,
+                generation: 0,
+            }),
+            generation_tx,
+        }
+    }
+
+    /// 等待凑齐 `n` 个任务
+    ///
+    /// 注意：这个 Future 在完成前被 drop 的话，它已经算作到达了，不会被撤销
+    pub async fn wait(&self) -> BarrierWaitResult {
+        let (mut rx, generation) = {
+            let mut state = self.state.lock().unwrap();
+            state.arrived += 1;
+            if state.arrived == self.n {
+                state.arrived = 0;
+                state.generation += 1;
+                self.generation_tx.send_replace(state.generation);
+                return BarrierWaitResult(true);
+            }
+            // 持有锁时订阅，保证不会错过这一代的结束
+            (self.generation_tx.subscribe(), state.generation)
+        };
+
+        while *rx.borrow_and_update() == generation {
+            // Sender 和 Barrier 活得一样久，changed 不会返回 Err
+            let _ = rx.changed().await;
+        }
+        BarrierWaitResult(false)
+    }
+}
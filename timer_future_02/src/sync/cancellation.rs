@@ -0,0 +1,202 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::future::{self, Either};
+
+use super::notify::Notify;
+
+/// 协作式取消的令牌
+///
+/// 任务本身不能被强制停止，只能在自己的 `.await` 点检查是否该结束了。
+/// 比如用 `Spawner::spawn` 生成的任务可以这样写：
+///
+/// ```ignore
+/// let token = CancellationToken::new();
+/// let child = token.child_token();
+/// spawner.spawn(async move {
+///     match child.run_until_cancelled(do_work()).await {
+///         Some(_) => println!("work finished"),
+///         None => println!("cancelled, cleaning up"),
+///     }
+/// });
+/// // 需要关闭时
+/// token.cancel();
+/// ```
+///
+/// clone 出来的令牌共享同一个状态；`child_token` 创建的子令牌会在父令牌被取消时一起被取消，
+/// 但取消子令牌不会影响父令牌
+#[derive(Clone)]
+pub struct CancellationToken {
+    node: Arc<Node>,
+}
+
+/*
+    子令牌用强引用指向父令牌，父令牌不记录子令牌：
+    - 判断是否被取消时沿着父链往上找，任何一个祖先被取消了就算被取消了，
+      中间的令牌全部 drop 了也不影响，因为它的子令牌还拿着它
+    - 等待取消时同时等待整条父链上每个节点的通知
+    - 子令牌全部 drop 后就被释放了，不会因为父令牌而一直占着内存
+*/
+struct Node {
+    parent: Option<Arc<Node>>,
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Node {
+    fn new(parent: Option<Arc<Node>>) -> Arc<Self> {
+        Arc::new(Node {
+            parent,
+            cancelled: AtomicBool::new(false),
+            notify: Notify::new(),
+        })
+    }
+
+    // 自己和所有的祖先，从近到远
+    fn ancestors(&self) -> impl Iterator<Item = &Node> {
+        std::iter::successors(Some(self), |node| node.parent.as_deref())
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken {
+            node: Node::new(None),
+        }
+    }
+
+    /// 创建一个子令牌，父令牌（包括它的所有 clone）被取消时，子令牌也会被取消
+    pub fn child_token(&self) -> CancellationToken {
+        CancellationToken {
+            node: Node::new(Some(self.node.clone())),
+        }
+    }
+
+    /// 取消这个令牌和它的所有子令牌，重复调用没有效果
+    pub fn cancel(&self) {
+        if !self.node.cancelled.swap(true, Ordering::SeqCst) {
+            self.node.notify.notify_waiters();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node
+            .ancestors()
+            .any(|node| node.cancelled.load(Ordering::SeqCst))
+    }
+
+    /// 等待令牌被取消
+    pub async fn cancelled(&self) {
+        loop {
+            // 先创建 Notified 再检查状态，这样检查之后发生的取消不会被错过
+            let notified: Vec<_> = self
+                .node
+                .ancestors()
+                .map(|node| Box::pin(node.notify.notified()))
+                .collect();
+            if self.is_cancelled() {
+                return;
+            }
+            future::select_all(notified).await;
+        }
+    }
+
+    /// 运行 `fut`，如果令牌先被取消就放弃它（drop 掉）并返回 None
+    pub async fn run_until_cancelled<F: Future>(&self, fut: F) -> Option<F::Output> {
+        let fut = std::pin::pin!(fut);
+        let cancelled = std::pin::pin!(self.cancelled());
+        match future::select(cancelled, fut).await {
+            Either::Left(((), _)) => None,
+            Either::Right((output, _)) => Some(output),
+        }
+    }
+
+    /// 返回一个 guard，guard 被 drop 时取消令牌，适合“离开作用域就关闭所有子任务”的场景
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard { token: Some(self) }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+/// `CancellationToken::drop_guard` 返回的 guard
+pub struct DropGuard {
+    token: Option<CancellationToken>,
+}
+
+impl DropGuard {
+    /// 拿回令牌，之后 drop 不会再取消它
+    pub fn disarm(mut self) -> CancellationToken {
+        self.token.take().unwrap()
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn cancelling_parent_cancels_children() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        let sibling = parent.child_token();
+
+        child.cancel();
+        assert!(grandchild.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+
+        parent.cancel();
+        assert!(sibling.is_cancelled());
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn dropping_an_intermediate_token_keeps_descendants_linked() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        drop(child);
+
+        let waiter = grandchild.clone();
+        let handle = thread::spawn(move || block_on(waiter.cancelled()));
+        thread::sleep(Duration::from_millis(20));
+        parent.cancel();
+        assert!(grandchild.is_cancelled());
+        // 已经在等待的任务也会被唤醒
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn run_until_cancelled_stops_waiting_work() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let output = block_on(token.run_until_cancelled(future::pending::<()>()));
+        assert_eq!(output, None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use super::semaphore::Semaphore;
+
+/// 异步互斥锁
+///
+/// 和 `std::sync::Mutex` 不同，`lock().await` 拿不到锁时只会让出当前任务，不会阻塞线程，
+/// 并且返回的 guard 可以跨 `.await` 持有
+pub struct Mutex<T: ?Sized> {
+    semaphore: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+// 和 std 的 Mutex 一样：只要 T 可以在线程间移动，锁就可以在线程间共享，
+// 因为同一时刻只有拿到唯一许可的那个 guard 能访问数据
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// 持有锁期间可以访问数据，drop 时释放锁
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    lock: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+/// 持有 `Arc<Mutex<T>>` 的 guard，可以移动到 spawn 出去的任务里
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct OwnedMutexGuard<T: ?Sized> {
+    lock: Arc<Mutex<T>>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for OwnedMutexGuard<T> {}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Mutex {
+            semaphore: Semaphore::new(1),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// 获取锁，多个任务同时等待时按先来后到的顺序获得
+    ///
+    /// 在拿到锁之前取消（drop 这个 Future）是安全的，不会丢失唤醒，也不会让锁一直被占着
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        // 信号量只在 Mutex 内部使用，永远不会被关闭
+        self.semaphore.acquire().await.unwrap().forget();
+        MutexGuard { lock: self }
+    }
+
+    /// 获取锁，返回的 guard 持有 `Arc`，不受 `&self` 生命周期的限制
+    pub async fn lock_owned(self: Arc<Self>) -> OwnedMutexGuard<T> {
+        self.semaphore.acquire().await.unwrap().forget();
+        OwnedMutexGuard { lock: self }
+    }
+
+    /// 不等待，锁被占用时返回 None
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire().ok()?;
+        permit.forget();
+        Some(MutexGuard { lock: self })
+    }
+
+    /// 有 `&mut self` 时不需要加锁
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(1);
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedMutexGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedMutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexGuard<T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(1);
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn guard_can_be_held_across_await() {
+        let counter = Arc::new(Mutex::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        for _ in 0..50 {
+                            let mut guard = counter.lock().await;
+                            let current = *guard;
+                            // 持有锁的同时等待另一个 Future
+                            futures::future::ready(()).await;
+                            *guard = current + 1;
+                        }
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*block_on(counter.lock()), 200);
+    }
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let mutex = Arc::new(Mutex::new(()));
+        let guard = block_on(mutex.clone().lock_owned());
+        assert!(mutex.try_lock().is_none());
+        let waiter = {
+            let mutex = mutex.clone();
+            thread::spawn(move || block_on(async { drop(mutex.lock().await) }))
+        };
+        thread::sleep(Duration::from_millis(20));
+        drop(guard);
+        waiter.join().unwrap();
+        assert!(mutex.try_lock().is_some());
+    }
+}
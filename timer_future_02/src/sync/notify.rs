@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+/// 让一个任务通知另一个（或所有）任务“可以继续了”，本身不携带数据
+///
+/// - `notify_one`：唤醒排在最前面的等待者；如果当时没有人在等，就存一个许可，下一个 `notified()` 会立即完成
+/// - `notify_waiters`：唤醒调用时已经创建的所有 `Notified`，不会存许可
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+struct State {
+    /// `notify_one` 在没有等待者时留下的许可，最多一个
+    permit: bool,
+
+    /// 每次 `notify_waiters` 加 1，`Notified` 创建时记下当时的值，不一样了就说明被通知过
+    generation: u64,
+
+    waiters: VecDeque<(u64, Waker)>,
+
+    /// 被 `notify_one` 选中、但还没有被 poll 到的等待者
+    notified: HashSet<u64>,
+
+    next_id: u64,
+}
+
+/// `Notify::notified` 返回的 Future
+///
+/// 在它第一次被 poll 之前调用的 `notify_waiters` 也会让它完成，
+/// 所以可以先创建 `Notified`，再检查条件，然后 `.await`，不会错过通知
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    generation: u64,
+
+    /// 排队时的编号
+    id: Option<u64>,
+    done: bool,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Notify {
+            state: Mutex::new(State {
+                permit: false,
+                generation: 0,
+                waiters: VecDeque::new(),
+                notified: HashSet::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// 等待通知
+    pub fn notified(&self) -> Notified<'_> {
+        let generation = self.state.lock().unwrap().generation;
+        Notified {
+            notify: self,
+            generation,
+            id: None,
+            done: false,
+        }
+    }
+
+    /// 通知一个等待者，没有等待者时存一个许可
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock().unwrap();
+        Self::notify_one_locked(&mut state);
+    }
+
+    fn notify_one_locked(state: &mut State) {
+        match state.waiters.pop_front() {
+            Some((id, waker)) => {
+                state.notified.insert(id);
+                waker.wake();
+            }
+            None => state.permit = true,
+        }
+    }
+
+    /// 通知所有当前的等待者
+    pub fn notify_waiters(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        // 被 notify_one 选中、还没 poll 到的等待者这次会因为 generation 变了而完成，
+        // 清掉它们的编号，把那次 notify_one 转成许可留给下一个 notified()，不能让它丢失
+        if !state.notified.is_empty() {
+            state.notified.clear();
+            state.permit = true;
+        }
+        for (_, waker) in state.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Notify::new()
+    }
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.done {
+            return Poll::Ready(());
+        }
+        let notify = self.notify;
+        let mut state = notify.state.lock().unwrap();
+
+        let woken = state.generation != self.generation
+            || match self.id {
+                Some(id) => state.notified.remove(&id),
+                None => std::mem::take(&mut state.permit),
+            };
+        if woken {
+            if let Some(id) = self.id.take() {
+                state.waiters.retain(|(waiter_id, _)| *waiter_id != id);
+            }
+            self.done = true;
+            return Poll::Ready(());
+        }
+
+        match self.id {
+            Some(id) => {
+                if let Some(entry) = state.waiters.iter_mut().find(|(waiter_id, _)| *waiter_id == id) {
+                    entry.1 = cx.waker().clone();
+                }
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back((id, cx.waker().clone()));
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else { return };
+        let mut state = self.notify.state.lock().unwrap();
+        if state.notified.remove(&id) {
+            // 被 notify_one 选中了却没来得及处理就被取消，把这次通知转交出去，不能让它丢失
+            Notify::notify_one_locked(&mut state);
+        } else {
+            state.waiters.retain(|(waiter_id, _)| *waiter_id != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+
+    #[test]
+    fn notify_one_before_wait_stores_permit() {
+        let notify = Notify::new();
+        notify.notify_one();
+        assert!(poll_once(&mut notify.notified()).is_ready());
+        assert!(poll_once(&mut notify.notified()).is_pending());
+    }
+
+    #[test]
+    fn notify_waiters_wakes_futures_created_before_it() {
+        let notify = Notify::new();
+        let mut a = notify.notified();
+        let mut b = notify.notified();
+        assert!(poll_once(&mut a).is_pending());
+        notify.notify_waiters();
+        assert!(poll_once(&mut a).is_ready());
+        assert!(poll_once(&mut b).is_ready());
+        // notify_waiters 不留许可
+        assert!(poll_once(&mut notify.notified()).is_pending());
+    }
+
+    #[test]
+    fn cancelled_waiter_passes_notification_on() {
+        let notify = Notify::new();
+        let mut first = notify.notified();
+        let mut second = notify.notified();
+        assert!(poll_once(&mut first).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+        notify.notify_one();
+        drop(first);
+        assert!(poll_once(&mut second).is_ready());
+    }
+
+    #[test]
+    fn notify_one_is_kept_when_notify_waiters_follows() {
+        let notify = Notify::new();
+        let mut waiter = notify.notified();
+        assert!(poll_once(&mut waiter).is_pending());
+        notify.notify_one();
+        notify.notify_waiters();
+        assert!(notify.state.lock().unwrap().notified.is_empty());
+
+        assert!(poll_once(&mut waiter).is_ready());
+        // notify_waiters 已经唤醒了 waiter，notify_one 留给下一个等待者
+        assert!(poll_once(&mut notify.notified()).is_ready());
+        assert!(poll_once(&mut notify.notified()).is_pending());
+    }
+}
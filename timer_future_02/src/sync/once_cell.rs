@@ -0,0 +1,144 @@
+use std::{fmt, future::Future, sync::OnceLock};
+
+use super::semaphore::Semaphore;
+
+/// 只能被初始化一次的值，初始化过程可以是异步的
+///
+/// 多个任务同时调用 `get_or_init` 时，只有排在最前面的那个会运行初始化的 Future，
+/// 其他的任务在 Semaphore 上排队等待，等它完成后直接拿到同一个值。
+/// 正在初始化的任务被取消（Future 被 drop）或者 `get_or_try_init` 失败时，
+/// 许可被还回去，下一个等待者接着用自己的初始化函数再试一次
+pub struct OnceCell<T> {
+    value: OnceLock<T>,
+    init: Semaphore,
+}
+
+impl<T> OnceCell<T> {
+    pub fn new() -> Self {
+        OnceCell {
+            value: OnceLock::new(),
+            init: Semaphore::new(1),
+        }
+    }
+
+    /// 已经初始化过时返回值
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    pub fn initialized(&self) -> bool {
+        self.value.get().is_some()
+    }
+
+    /// 直接设置值，已经初始化过时把 `value` 还回去
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.value.set(value)
+    }
+
+    /// 返回值，还没有初始化时用 `init` 返回的 Future 初始化
+    pub async fn get_or_init<F, Fut>(&self, init: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        match self
+            .get_or_try_init(|| async { Ok::<_, std::convert::Infallible>(init().await) })
+            .await
+        {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// 和 get_or_init 一样，只是初始化可能失败，失败时不保存任何值，下一次调用会重新初始化
+    pub async fn get_or_try_init<F, Fut, E>(&self, init: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        // 信号量从来不会被关闭
+        let _permit = self.init.acquire().await.unwrap();
+        // 排队的时候前面的任务可能已经初始化好了
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = init().await?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        OnceCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceCell")
+            .field("value", &self.value.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel::oneshot, test_util::poll_once};
+    use futures::{executor::block_on, future::join_all};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn concurrent_initializers_coalesce() {
+        let cell = OnceCell::new();
+        let runs = AtomicUsize::new(0);
+        let (tx, rx) = oneshot::channel();
+        let mut rx = Some(rx);
+
+        let values = block_on(async {
+            let callers = (0..5).map(|_| {
+                let (cell, runs) = (&cell, &runs);
+                // 只有第一个调用者的初始化函数会用到 rx
+                let rx = rx.take();
+                async move {
+                    cell.get_or_init(|| async move {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        rx.unwrap().await.unwrap()
+                    })
+                    .await
+                }
+            });
+            let sender = async move {
+                tx.send(42).unwrap();
+            };
+            let (values, ()) = futures::join!(join_all(callers), sender);
+            values
+        });
+        assert!(values.iter().all(|value| **value == 42));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(cell.get(), Some(&42));
+    }
+
+    #[test]
+    fn failed_or_cancelled_init_lets_the_next_caller_retry() {
+        let cell = OnceCell::new();
+        let failed = block_on(cell.get_or_try_init(|| async { Err::<u32, _>("boom") }));
+        assert_eq!(failed, Err("boom"));
+        assert!(!cell.initialized());
+
+        // 初始化到一半被 drop 掉
+        let mut pending = Box::pin(cell.get_or_init(futures::future::pending));
+        assert!(poll_once(&mut pending).is_pending());
+        drop(pending);
+
+        assert_eq!(block_on(cell.get_or_init(|| async { 7 })), &7);
+        assert_eq!(cell.set(8), Err(8));
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::semaphore::Semaphore;
+
+// 同时持有读锁的最大数量，写锁需要一次拿走全部许可
+const MAX_READS: usize = u32::MAX as usize >> 3;
+
+/// 异步读写锁
+///
+/// 读锁拿 1 个许可，写锁拿 `MAX_READS` 个许可。因为信号量是公平的，
+/// 一个写者开始排队后，后来的读者会排在它后面，所以写者不会被源源不断的读者饿死
+pub struct RwLock<T: ?Sized> {
+    semaphore: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+// 多个读者会同时拿到 &T，所以 Sync 还要求 T: Sync
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+/// 读锁，可以有多个同时存在
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+/// 写锁，持有期间没有其他读者或写者
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        RwLock {
+            semaphore: Semaphore::new(MAX_READS),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// 获取读锁
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.semaphore.acquire().await.unwrap().forget();
+        RwLockReadGuard { lock: self }
+    }
+
+    /// 获取写锁
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.semaphore.acquire_many(MAX_READS).await.unwrap().forget();
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// 不等待，拿不到读锁时返回 None
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.semaphore.try_acquire().ok()?.forget();
+        Some(RwLockReadGuard { lock: self })
+    }
+
+    /// 不等待，拿不到写锁时返回 None
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.semaphore.try_acquire_many(MAX_READS).ok()?.forget();
+        Some(RwLockWriteGuard { lock: self })
+    }
+
+    /// 有 `&mut self` 时不需要加锁
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(1);
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(MAX_READS);
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+    use std::task::Poll;
+
+    #[test]
+    fn waiting_writer_goes_before_later_readers() {
+        let lock = RwLock::new(0);
+        let reader = lock.try_read().unwrap();
+
+        let mut write = Box::pin(lock.write());
+        let mut read = Box::pin(lock.read());
+        assert!(poll_once(&mut write).is_pending());
+        // 现在只有读者持有锁，但写者在排队，后来的读者排在写者后面
+        assert!(poll_once(&mut read).is_pending());
+        assert!(lock.try_read().is_none());
+
+        drop(reader);
+        let mut writer = match poll_once(&mut write) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("writer should get the lock first"),
+        };
+        assert!(poll_once(&mut read).is_pending());
+        *writer = 1;
+        drop(writer);
+        match poll_once(&mut read) {
+            Poll::Ready(guard) => assert_eq!(*guard, 1),
+            Poll::Pending => panic!("reader should get the lock after the writer"),
+        };
+    }
+
+    #[test]
+    fn cancelled_writer_does_not_keep_the_lock() {
+        let lock = RwLock::new(());
+
+        // 还在排队时被取消，排在后面的读者直接拿到锁
+        let reader = lock.try_read().unwrap();
+        let mut write = Box::pin(lock.write());
+        let mut read = Box::pin(lock.read());
+        assert!(poll_once(&mut write).is_pending());
+        assert!(poll_once(&mut read).is_pending());
+        drop(write);
+        assert!(poll_once(&mut read).is_ready());
+        drop(read);
+
+        // 已经分到了锁、还没有被 poll 就被取消，许可要还回去
+        let mut write = Box::pin(lock.write());
+        assert!(poll_once(&mut write).is_pending());
+        drop(reader);
+        drop(write);
+        assert!(lock.try_write().is_some());
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// 公平的异步信号量
+///
+/// 等待者严格按照先来后到获得许可：排在前面的等待者需要的许可还不够时，
+/// 后面的等待者即使只需要 1 个许可也要继续等，这样需要很多许可的一方（比如写锁）不会被饿死
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    /// 当前空闲的许可数
+    permits: usize,
+
+    /// 排队中的等待者
+    waiters: VecDeque<Waiter>,
+
+    /// 已经分配到许可、但还没有被 poll 取走的等待者
+    granted: HashSet<u64>,
+
+    next_id: u64,
+    closed: bool,
+}
+
+struct Waiter {
+    id: u64,
+    needed: usize,
+    waker: Waker,
+}
+
+impl State {
+    // 按排队顺序把空闲的许可分配给等待者，并唤醒拿到许可的任务
+    fn assign_permits(&mut self) {
+        while let Some(front) = self.waiters.front() {
+            if front.needed > self.permits {
+                break;
+            }
+            let waiter = self.waiters.pop_front().unwrap();
+            self.permits -= waiter.needed;
+            self.granted.insert(waiter.id);
+            waiter.waker.wake();
+        }
+    }
+}
+
+/// 信号量已经被关闭
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcquireError;
+
+/// `try_acquire` 失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryAcquireError {
+    /// 信号量已经被关闭
+    Closed,
+    /// 暂时没有足够的许可（或者前面还有人在排队）
+    NoPermits,
+}
+
+impl Semaphore {
+    /// 创建一个有 `permits` 个许可的信号量
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+                granted: HashSet::new(),
+                next_id: 0,
+                closed: false,
+            }),
+        }
+    }
+
+    /// 当前空闲的许可数
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    /// 增加许可，可能会唤醒正在等待的任务
+    pub fn add_permits(&self, n: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.permits += n;
+        state.assign_permits();
+    }
+
+    /// 关闭信号量：正在等待和之后的 acquire 都会返回 `AcquireError`，已经拿到的许可不受影响
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        for waiter in state.waiters.drain(..) {
+            waiter.waker.wake();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// 获取 1 个许可
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// 一次获取 `n` 个许可，要么全部拿到，要么一个都不拿
+    pub fn acquire_many(&self, n: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            needed: n,
+            id: None,
+        }
+    }
+
+    /// 获取 1 个许可，返回的许可持有信号量的 `Arc`，可以移动到别的任务中
+    pub async fn acquire_owned(self: Arc<Self>) -> Result<OwnedSemaphorePermit, AcquireError> {
+        self.acquire_many(1).await?.forget();
+        Ok(OwnedSemaphorePermit {
+            semaphore: self,
+            permits: 1,
+        })
+    }
+
+    /// 不等待，直接尝试获取 1 个许可
+    pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        self.try_acquire_many(1)
+    }
+
+    /// 不等待，直接尝试获取 `n` 个许可
+    pub fn try_acquire_many(&self, n: usize) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(TryAcquireError::Closed);
+        }
+        // 前面有人在排队时不能插队
+        if state.waiters.is_empty() && state.permits >= n {
+            state.permits -= n;
+            Ok(SemaphorePermit {
+                semaphore: self,
+                permits: n,
+            })
+        } else {
+            Err(TryAcquireError::NoPermits)
+        }
+    }
+
+    fn release(&self, n: usize) {
+        self.add_permits(n);
+    }
+}
+
+/// `acquire` 返回的 Future
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    needed: usize,
+
+    /// 排队时的编号，None 表示还没有排队
+    id: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Result<SemaphorePermit<'a>, AcquireError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        let needed = self.needed;
+        let permit = || SemaphorePermit {
+            semaphore,
+            permits: needed,
+        };
+        let mut state = semaphore.state.lock().unwrap();
+
+        match self.id {
+            Some(id) => {
+                if state.granted.remove(&id) {
+                    self.id = None;
+                    return Poll::Ready(Ok(permit()));
+                }
+                if state.closed {
+                    self.id = None;
+                    return Poll::Ready(Err(AcquireError));
+                }
+                // 还在排队，更新 waker
+                if let Some(waiter) = state.waiters.iter_mut().find(|w| w.id == id) {
+                    if !waiter.waker.will_wake(cx.waker()) {
+                        waiter.waker = cx.waker().clone();
+                    }
+                }
+                Poll::Pending
+            }
+            None => {
+                if state.closed {
+                    return Poll::Ready(Err(AcquireError));
+                }
+                if state.waiters.is_empty() && state.permits >= needed {
+                    state.permits -= needed;
+                    return Poll::Ready(Ok(permit()));
+                }
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back(Waiter {
+                    id,
+                    needed,
+                    waker: cx.waker().clone(),
+                });
+                self.id = Some(id);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else { return };
+        let mut state = self.semaphore.state.lock().unwrap();
+        if state.granted.remove(&id) {
+            // 许可已经分给我们了，但我们被取消了，把许可还回去
+            state.permits += self.needed;
+        } else {
+            state.waiters.retain(|w| w.id != id);
+        }
+        // 不管是哪种情况，排在后面的等待者都可能因此拿到许可
+        state.assign_permits();
+    }
+}
+
+/// 借用信号量的许可，drop 时归还
+#[must_use]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// 不归还许可，信号量的许可数就永久减少了
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+/// 持有信号量 `Arc` 的许可，drop 时归还
+#[must_use]
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+}
+
+impl OwnedSemaphorePermit {
+    /// 不归还许可，信号量的许可数就永久减少了
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("waiters", &state.waiters.len())
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "semaphore closed")
+    }
+}
+
+impl Error for AcquireError {}
+
+impl fmt::Display for TryAcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryAcquireError::Closed => write!(f, "semaphore closed"),
+            TryAcquireError::NoPermits => write!(f, "no permits available"),
+        }
+    }
+}
+
+impl Error for TryAcquireError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+
+    #[test]
+    fn waiters_are_served_in_fifo_order() {
+        let sem = Semaphore::new(2);
+        let held = sem.try_acquire_many(2).unwrap();
+
+        let mut big = sem.acquire_many(2);
+        let mut small = sem.acquire();
+        assert!(poll_once(&mut big).is_pending());
+        assert!(poll_once(&mut small).is_pending());
+
+        drop(held);
+        // 排在前面的 big 先拿到全部许可，small 必须继续等
+        let big_permit = match poll_once(&mut big) {
+            Poll::Ready(Ok(permit)) => permit,
+            _ => panic!("first waiter should get permits"),
+        };
+        assert!(poll_once(&mut small).is_pending());
+        drop(big_permit);
+        assert!(matches!(poll_once(&mut small), Poll::Ready(Ok(_))));
+    }
+
+    #[test]
+    fn cancelled_acquire_gives_permits_back() {
+        let sem = Semaphore::new(1);
+        let held = sem.try_acquire().unwrap();
+        let mut first = sem.acquire();
+        let mut second = sem.acquire();
+        assert!(poll_once(&mut first).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+
+        drop(held); // 许可分配给了 first
+        drop(first); // first 被取消，许可转给 second
+        assert!(matches!(poll_once(&mut second), Poll::Ready(Ok(_))));
+    }
+
+    #[test]
+    fn close_fails_pending_acquires() {
+        let sem = Semaphore::new(0);
+        let mut acquire = sem.acquire();
+        assert!(poll_once(&mut acquire).is_pending());
+        sem.close();
+        assert!(matches!(poll_once(&mut acquire), Poll::Ready(Err(AcquireError))));
+        assert_eq!(sem.try_acquire().err(), Some(TryAcquireError::Closed));
+    }
+}
@@ -0,0 +1,15 @@
+// 测试中共用的小工具
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::task::noop_waker;
+
+/// 用不会唤醒任何任务的 waker poll 一次，用来把 Future 推进到第一个 Pending（比如开始排队）
+pub(crate) fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let waker = noop_waker();
+    Pin::new(future).poll(&mut Context::from_waker(&waker))
+}
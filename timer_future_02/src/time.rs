@@ -0,0 +1,267 @@
+/*
+    定时器
+
+    TimerFuture 为每个定时器生成一个线程，用来演示 waker 的原理很直观，
+    但重试、限流这类代码会不停地创建定时器，每次都开一个线程就太浪费了。
+
+    这里和 Reactor 的思路一样，只用一个后台线程管理所有的定时器：
+    1. Sleep 第一次 poll 时把 (到期时间, id) 放进一个最小堆，waker 记在 id 对应的表项里
+    2. 后台线程用 Condvar 等到堆顶的到期时间（有更早的定时器加进来时会被提前唤醒）
+    3. 时间到了就取出所有到期的表项，调用它们的 waker
+    4. Sleep 在到期前被 drop 时只删除表项，堆中留下的旧记录在到期时被跳过
+
+    因为只和 Waker 打交道，所以和 channel、sync 一样可以配合任何执行者使用
+*/
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    error::Error,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::future::{self, Either};
+
+struct Driver {
+    state: Mutex<DriverState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct DriverState {
+    /// 按到期时间排列的 (到期时间, id)，可能有已经被删除或者重设过的旧记录
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+
+    /// 还在等待的定时器
+    timers: HashMap<u64, Timer>,
+    next_id: u64,
+}
+
+struct Timer {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl Driver {
+    // 全局唯一的定时器线程，第一次使用时启动
+    fn get() -> &'static Driver {
+        static DRIVER: OnceLock<Driver> = OnceLock::new();
+        DRIVER.get_or_init(|| {
+            thread::Builder::new()
+                .name("timer_future_02-timer".into())
+                .spawn(|| Driver::get().run())
+                .expect("failed to spawn timer thread");
+            Driver {
+                state: Mutex::new(DriverState::default()),
+                condvar: Condvar::new(),
+            }
+        })
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+            while let Some(&Reverse((deadline, id))) = state.heap.peek() {
+                if deadline > now {
+                    break;
+                }
+                state.heap.pop();
+                // 被 reset 到更晚的时间的定时器不在这时唤醒
+                if state
+                    .timers
+                    .get(&id)
+                    .is_some_and(|timer| timer.deadline <= now)
+                {
+                    expired.push(state.timers.remove(&id).unwrap().waker);
+                }
+            }
+
+            if !expired.is_empty() {
+                // 在锁外面唤醒，wake 可能会直接 poll 任务，任务又会创建新的定时器
+                drop(state);
+                expired.into_iter().for_each(Waker::wake);
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state = match state.heap.peek() {
+                Some(&Reverse((deadline, _))) => {
+                    self.condvar
+                        .wait_timeout(state, deadline.saturating_duration_since(now))
+                        .unwrap()
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    // 登记或者更新一个定时器，返回它的 id
+    fn register(&self, id: Option<u64>, deadline: Instant, waker: &Waker) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = id.unwrap_or_else(|| {
+            state.next_id += 1;
+            state.next_id
+        });
+        let earliest = state.heap.peek().map(|&Reverse((deadline, _))| deadline);
+        match state.timers.get_mut(&id) {
+            Some(timer) if timer.deadline == deadline => {
+                if !timer.waker.will_wake(waker) {
+                    timer.waker = waker.clone();
+                }
+                return id;
+            }
+            _ => {
+                state.timers.insert(
+                    id,
+                    Timer {
+                        deadline,
+                        waker: waker.clone(),
+                    },
+                );
+                state.heap.push(Reverse((deadline, id)));
+            }
+        }
+        // 新的定时器比现在等待的更早到期，让后台线程重新计算等待时间
+        if earliest.is_none_or(|earliest| deadline < earliest) {
+            self.condvar.notify_one();
+        }
+        id
+    }
+
+    fn remove(&self, id: u64) {
+        self.state.lock().unwrap().timers.remove(&id);
+    }
+}
+
+/// `sleep`、`sleep_until` 返回的 Future
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    id: Option<u64>,
+}
+
+/// 等待 `duration` 这么长的时间
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// 等待到 `deadline`，已经过了的时间立刻完成
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, id: None }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// 改成在 `deadline` 到期，可以在完成之后重新使用
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_elapsed() {
+            if let Some(id) = self.id.take() {
+                Driver::get().remove(id);
+            }
+            return Poll::Ready(());
+        }
+        let id = Driver::get().register(self.id, self.deadline, cx.waker());
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            Driver::get().remove(id);
+        }
+    }
+}
+
+/// `timeout` 超时时返回的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+// 这样返回 io::Result 的函数中可以直接对 timeout 的结果使用 `?`
+impl From<Elapsed> for io::Error {
+    fn from(elapsed: Elapsed) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, elapsed)
+    }
+}
+
+/// 给 `future` 加上时间限制，超时时 drop 掉它并返回 Elapsed
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    let future = std::pin::pin!(future);
+    match future::select(future, sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(((), _)) => Err(Elapsed(())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::poll_once;
+    use futures::{executor::block_on, future::join_all};
+
+    #[test]
+    fn sleeps_wake_in_deadline_order() {
+        let start = Instant::now();
+        let order = std::sync::Mutex::new(Vec::new());
+        block_on(join_all([60u64, 20, 40].map(|millis| {
+            let order = &order;
+            async move {
+                sleep(Duration::from_millis(millis)).await;
+                order.lock().unwrap().push(millis);
+            }
+        })));
+        assert_eq!(*order.lock().unwrap(), [20, 40, 60]);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+
+        // 已经过了的时间立刻完成
+        block_on(sleep_until(start));
+    }
+
+    #[test]
+    fn dropped_sleep_is_removed_and_timeout_cancels() {
+        let mut early = Box::pin(sleep(Duration::from_secs(60)));
+        assert!(poll_once(&mut early).is_pending());
+        let id = early.id.unwrap();
+        drop(early);
+        assert!(!Driver::get().state.lock().unwrap().timers.contains_key(&id));
+
+        let slow = timeout(Duration::from_millis(20), sleep(Duration::from_secs(60)));
+        assert_eq!(block_on(slow), Err(Elapsed(())));
+        let fast = timeout(Duration::from_secs(60), async { 7 });
+        assert_eq!(block_on(fast), Ok(7));
+    }
+}
@@ -1,7 +1,12 @@
-use async_std::{ net::{TcpListener, TcpStream}, prelude::*, task::{self, spawn} };
-use futures::stream::StreamExt;
-use std::fs;
+use async_q_07::service::{Service, ServiceExt, Timeout, TimeoutLayer};
+use async_std::{ fs, net::{TcpListener, TcpStream}, prelude::*, task::{self, spawn} };
+use futures::{future::BoxFuture, stream::StreamExt};
+use std::io;
+use std::net::IpAddr;
+use std::sync::LazyLock;
 use std::time::Duration;
+use timer_future_02::cache::Cache;
+use timer_future_02::rate_limit::{Keyed, RateLimiter, TokenBucket};
 
 // use for tests 
 use async_std::io::{Read, Write};
@@ -23,10 +28,92 @@ async fn main() {
         .for_each_concurrent(/* limit */ None, |tcpstream| async move {
             let tcpstream = tcpstream.unwrap();
             //handle_connection(tcpstream).await;
-            spawn(handle_connection(tcpstream));
+            spawn(serve(tcpstream));
         })
         .await;
 }
+
+/*
+    每个客户端 IP 一个令牌桶：平均每秒 10 个请求，最多一次突发 20 个，
+    超过的请求直接返回 429，不占用处理页面的资源。
+    客户端很多时桶也会很多，超过 1024 个时删掉已经攒满（一段时间没有请求）的桶
+ */
+static LIMITS: LazyLock<Keyed<IpAddr, TokenBucket>> =
+    LazyLock::new(|| Keyed::new(|| TokenBucket::new(10, Duration::from_secs(1)).burst(20)));
+
+async fn serve(mut stream: TcpStream) {
+    if LIMITS.len() > 1024 {
+        LIMITS.prune();
+    }
+    let allowed = match stream.peer_addr() {
+        Ok(addr) => LIMITS.get(addr.ip()).try_acquire(),
+        Err(_) => true,
+    };
+    if allowed {
+        handle_connection(stream).await;
+    } else {
+        let _ = stream.write_all(b"HTTP/1.1 429 TOO MANY REQUESTS\r\n\r\n").await;
+    }
+}
+/*
+    之前每个请求都要从磁盘重新读一遍 html 文件，后来改成第一次用到时读一次、之后一直用（OnceCell），
+    但那样改了 html 之后必须重启服务器。现在文件内容放在 Cache 里：
+    - 缓存 10 秒，同时到达的多个请求只有一个真正去读文件，其他的等它读完；读取失败时不缓存，下一个请求再试
+    - 过期后的 1 分钟内先返回旧的内容，同时在后台重新读取，请求不用等磁盘
+ */
+static FILES: LazyLock<Cache<&'static str, String>> = LazyLock::new(|| {
+    Cache::new(16)
+        .ttl(Duration::from_secs(10))
+        .stale_while_revalidate(Duration::from_secs(60))
+        .spawner(|future| {
+            spawn(future);
+        })
+});
+
+async fn page(filename: &'static str) -> io::Result<String> {
+    FILES.get_or_load(filename, move || fs::read_to_string(filename)).await
+}
+
+/// 请求行，比如 "GET / HTTP/1.1"
+struct Request {
+    line: String,
+}
+
+struct Response {
+    status_line: &'static str,
+    contents: String,
+}
+
+/*
+    根据请求行返回页面的处理函数写成一个 Service，
+    这样超时、限流这些和业务无关的逻辑可以用 Layer 包在外面，handle_connection 只管读写 TcpStream
+ */
+struct Pages;
+
+impl Service<Request> for Pages {
+    type Response = Response;
+    type Error = io::Error;
+
+    fn call(&self, req: Request) -> BoxFuture<'_, io::Result<Response>> {
+        Box::pin(async move {
+            let (status_line, filename) = match req.line.as_str() {
+                "GET / HTTP/1.1" => ("HTTP/1.1 200 OK\r\n\r\n", "hello.html"),
+                "GET /sleep HTTP/1.1" => {
+                    task::sleep(Duration::from_secs(5)).await;
+                    ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
+                }
+                _ => ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html"),
+            };
+            let contents = page(filename).await?;
+            Ok(Response { status_line, contents })
+        })
+    }
+}
+
+// 10 秒还没处理完的请求（比如读文件卡住了）返回 500
+static SERVICE: LazyLock<Timeout<Pages>> =
+    LazyLock::new(|| Pages.layer(TimeoutLayer::new(Duration::from_secs(10))));
+
 // TcpStream 来自 async_std，之前 TcpStream 是标准库的
 // async fn handle_connection(mut stream: TcpStream) {
 async fn handle_connection(mut stream: impl Read + Write + Unpin) {
@@ -34,20 +121,17 @@ async fn handle_connection(mut stream: impl Read + Write + Unpin) {
     let mut buffer = [0; 1024];
     stream.read(&mut buffer).await.unwrap(); // async version
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
+    let line = String::from_utf8_lossy(&buffer).lines().next().unwrap_or_default().to_string();
+    let result = async {
+        SERVICE.ready().await?;
+        SERVICE.call(Request { line }).await
+    }
+    .await;
 
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        task::sleep(Duration::from_secs(5)).await;
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html")
+    let response = match result {
+        Ok(Response { status_line, contents }) => format!("{status_line}{contents}"),
+        Err(_) => "HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n".to_string(),
     };
-    let contents = fs::read_to_string(filename).unwrap();
-
-    let response = format!("{status_line}{contents}");
     stream.write(response.as_bytes()).await.unwrap();
     stream.flush().await.unwrap();
 }
@@ -55,7 +139,6 @@ async fn handle_connection(mut stream: impl Read + Write + Unpin) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::io::Error;
     use futures::task::{Context, Poll};
     use std::cmp::min;
     use std::pin::Pin;
@@ -68,7 +151,7 @@ mod tests {
     impl Read for MockTcpStream {
         fn poll_read(
                     self: Pin<&mut Self>,
-                    cx: &mut Context<'_>,
+                    _cx: &mut Context<'_>,
                     buf: &mut [u8],
                 ) -> Poll<std::io::Result<usize>> {
                     // 读取 read_data 长度与 buffer 长度中比较小的值
@@ -84,7 +167,7 @@ mod tests {
         // 把数据写入 TcpStream，
         fn poll_write(mut
                     self: Pin<&mut Self>,
-                    cx: &mut Context<'_>,
+                    _cx: &mut Context<'_>,
                     buf: &[u8],
                 ) -> Poll<std::io::Result<usize>> {
                     self.write_data = Vec::from(buf);
@@ -92,11 +175,11 @@ mod tests {
         }
 
         // 针对 MockTcpStream 而言，poll_flush 和 poll_close 就没啥用，返回 Poll::Ready 就可以了
-        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
             Poll::Ready(Ok(()))
         }
 
-        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
             Poll::Ready(Ok(()))
         }
     }